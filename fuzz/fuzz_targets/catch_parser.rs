@@ -0,0 +1,34 @@
+//! Fuzz the parser that catches packages from command lines.
+//!
+//! Run with `cargo +nightly fuzz run catch_parser` from the root of the repository.
+#![no_main]
+// Emplace is only a binary so the parser modules are included directly, not everything is used
+#![allow(dead_code)]
+
+#[path = "../../src/package.rs"]
+mod package;
+#[path = "../../src/package_manager/mod.rs"]
+mod package_manager;
+#[path = "../../src/package_manager_impl.rs"]
+mod package_manager_impl;
+
+use libfuzzer_sys::fuzz_target;
+use package::{LocalFile, Packages};
+use package_manager::PackageManager;
+use strum::IntoEnumIterator;
+
+fuzz_target!(|line: &str| {
+    // Every caught package must come from the line itself
+    for package in Packages::parse_line(line).packages.iter() {
+        assert!(line.contains(package.name()));
+    }
+
+    LocalFile::from_line(line);
+
+    // A flag that invalidates the command must prevent anything from being caught
+    for manager in PackageManager::iter() {
+        if manager.parse(line).iter().all(|parse| parse.is_invalidated()) {
+            assert!(manager.catch(line).packages.is_empty());
+        }
+    }
+});
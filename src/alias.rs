@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use std::{collections::HashMap, fs, path::Path};
+
+/// Characters that separate multiple commands on a single line.
+const COMMAND_SEPARATORS: [char; 4] = [';', '|', '&', '\n'];
+
+/// Shell aliases as dumped by the `alias` builtin of the shell.
+#[derive(Debug, Default, Clone)]
+pub struct Aliases(HashMap<String, String>);
+
+impl Aliases {
+    /// Load the aliases from a file dumped by the shell hook, an empty map when it doesn't exist.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if !path.as_ref().exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path).context("reading aliases file")?;
+
+        Ok(Self::parse(&contents))
+    }
+
+    /// Parse the output of `alias` in bash, zsh or fish.
+    pub fn parse(contents: &str) -> Self {
+        Self(
+            contents
+                .lines()
+                .filter_map(|line| {
+                    // Bash & fish prefix every line with the builtin
+                    let line = line.trim();
+                    let line = line.strip_prefix("alias ").unwrap_or(line).trim_start();
+
+                    // Bash & zsh use `name=value`, fish uses `name value`
+                    let (name, value) = line.split_at(line.find(['=', ' '])?);
+                    let value = unquote(&value[1..]);
+                    if name.is_empty() || value.is_empty() {
+                        return None;
+                    }
+
+                    Some((name.to_string(), value))
+                })
+                .collect(),
+        )
+    }
+
+    /// Replace all the aliases in command positions with the commands they point to.
+    pub fn expand(&self, line: &str) -> String {
+        if self.0.is_empty() {
+            return line.to_string();
+        }
+
+        line.split_inclusive(COMMAND_SEPARATORS)
+            .map(|command| self.expand_command(command, &mut vec![]))
+            .collect()
+    }
+
+    /// Get the directory where the shell hooks dump their aliases, a file per shell named after
+    /// its process ID so shells don't overwrite each others aliases.
+    pub fn directory() -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(
+            dirs::cache_dir()
+                .expect("Could not find cache dir")
+                .join("emplace")
+                .join("aliases"),
+        )
+        .expect("Path is not valid UTF-8")
+    }
+
+    /// Expand the first word of a command, keeping track of the aliases already expanded to
+    /// prevent infinite recursion.
+    fn expand_command(&self, command: &str, expanded: &mut Vec<String>) -> String {
+        let trimmed = command.trim_start();
+        let leading = &command[..command.len() - trimmed.len()];
+
+        let (word, rest) = trimmed.split_at(
+            trimmed
+                .find(|c: char| c.is_whitespace() || COMMAND_SEPARATORS.contains(&c))
+                .unwrap_or(trimmed.len()),
+        );
+
+        match self.0.get(word) {
+            Some(value) if !expanded.iter().any(|alias| alias == word) => {
+                expanded.push(word.to_string());
+
+                // An alias ending with a space also expands the word after it, like `sudo `
+                let rest = if value.ends_with(' ') {
+                    self.expand_command(rest.trim_start(), &mut expanded.clone())
+                } else {
+                    rest.to_string()
+                };
+
+                format!(
+                    "{}{}{}",
+                    leading,
+                    self.expand_command(value, expanded),
+                    rest
+                )
+            }
+            _ => command.to_string(),
+        }
+    }
+}
+
+/// Remove the shell quoting from an alias value.
+fn unquote(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Everything in single quotes is literal
+            '\'' => result.extend(chars.by_ref().take_while(|c| *c != '\'')),
+            // Double quotes can still escape characters
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => result.extend(chars.next()),
+                        c => result.push(c),
+                    }
+                }
+            }
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aliases;
+
+    #[test]
+    fn test_parse() {
+        let bash = Aliases::parse(indoc::indoc! {r#"
+            alias i='sudo apt install'
+            alias ll='ls -l'
+            alias quote='echo '\''quoted'\'''
+        "#});
+        assert_eq!(bash.0["i"], "sudo apt install");
+        assert_eq!(bash.0["ll"], "ls -l");
+        assert_eq!(bash.0["quote"], "echo 'quoted'");
+
+        let zsh = Aliases::parse(indoc::indoc! {r#"
+            ci='cargo install --locked'
+            l=ls
+        "#});
+        assert_eq!(zsh.0["ci"], "cargo install --locked");
+        assert_eq!(zsh.0["l"], "ls");
+
+        let fish = Aliases::parse(indoc::indoc! {r#"
+            alias ci 'cargo install --locked'
+            alias i "sudo apt install"
+        "#});
+        assert_eq!(fish.0["ci"], "cargo install --locked");
+        assert_eq!(fish.0["i"], "sudo apt install");
+    }
+
+    #[test]
+    fn test_expand() {
+        let aliases = Aliases::parse(indoc::indoc! {r#"
+            alias i='sudo apt install'
+            alias ci='cargo install --locked'
+            alias s='sudo '
+            alias ls='ls --color'
+        "#});
+
+        assert_eq!(aliases.expand("i test"), "sudo apt install test");
+        assert_eq!(aliases.expand("ci test"), "cargo install --locked test");
+        assert_eq!(
+            aliases.expand("echo i; i test && ci test"),
+            "echo i; sudo apt install test && cargo install --locked test"
+        );
+        // Only the first word is expanded
+        assert_eq!(aliases.expand("echo i test"), "echo i test");
+        // Aliases ending in a space expand the next word
+        assert_eq!(aliases.expand("s i test"), "sudo sudo apt install test");
+        // Recursive aliases don't loop forever
+        assert_eq!(aliases.expand("ls"), "ls --color");
+    }
+}
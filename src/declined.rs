@@ -0,0 +1,250 @@
+use crate::{
+    lock::{FileLock, LOCK_TIMEOUT},
+    package::{Package, Packages},
+};
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use colored::Colorize;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Packages that the user chose not to mirror on this machine.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Declined {
+    /// Where the list is persisted.
+    #[serde(skip)]
+    path: PathBuf,
+    /// The packages that aren't asked about anymore.
+    #[serde(default)]
+    pub packages: Packages,
+}
+
+impl Declined {
+    /// Load the list from the default path.
+    pub fn load() -> Result<Self> {
+        Self::from_path(Self::default_path())
+    }
+
+    /// Change the list at the default path, making sure no other process changes it at the same
+    /// time.
+    pub fn modify<T, F>(f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Declined) -> T,
+    {
+        let _lock =
+            FileLock::acquire_timeout(FileLock::path_for(Self::default_path()), LOCK_TIMEOUT)?;
+
+        let mut declined = Self::load()?;
+        let result = f(&mut declined);
+        declined.save()?;
+
+        Ok(result)
+    }
+
+    /// Load the list from a file, an empty list when it doesn't exist.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                ..Self::default()
+            });
+        }
+
+        let contents = fs::read_to_string(&path).context("reading declined packages file")?;
+        let declined =
+            ron::de::from_str(&contents).context("deserializing declined packages file")?;
+
+        Ok(Self { path, ..declined })
+    }
+
+    /// Persist the list, removing the file when it's empty.
+    pub fn save(&self) -> Result<()> {
+        if self.packages.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path).context("removing empty declined packages file")?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("creating directory for declined packages file")?;
+        }
+
+        let contents = to_string_pretty(self, PrettyConfig::new().depth_limit(2))?;
+        fs::write(&self.path, contents).context("writing declined packages file")?;
+
+        Ok(())
+    }
+
+    /// Remember packages that shouldn't be asked about again.
+    pub fn push_packages(&mut self, packages: &Packages) {
+        self.packages.merge(&mut packages.clone());
+    }
+
+    /// Whether the package has been declined, with any flags.
+    pub fn contains(&self, package: &Package) -> bool {
+        self.packages.iter().any(|declined| {
+            declined.source() == package.source() && declined.name() == package.name()
+        })
+    }
+
+    /// Forget the packages with one of the names, returns the packages that are forgotten.
+    pub fn forget(&mut self, names: &[&str]) -> Packages {
+        let (forgotten, packages): (Vec<Package>, Vec<Package>) = self
+            .packages
+            .iter()
+            .cloned()
+            .partition(|package| names.contains(&package.name()));
+        self.packages = Packages::from(packages);
+
+        Packages::from(forgotten)
+    }
+
+    /// Get the default path where the list is stored, in the state directory of this machine so
+    /// it's never inside the mirrors repository and isn't removed together with the cache.
+    pub fn default_path() -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(
+            dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .or_else(dirs::config_dir)
+                .expect("Could not find state dir")
+                .join("emplace")
+                .join("declined.ron"),
+        )
+        .expect("Path is not valid UTF-8")
+    }
+}
+
+/// Remember that the packages shouldn't be mirrored on this machine.
+pub fn decline(packages: &Packages) -> Result<()> {
+    if packages.is_empty() {
+        return Ok(());
+    }
+
+    Declined::modify(|declined| declined.push_packages(packages))?;
+
+    println!(
+        "{}",
+        "You won't be asked about this again, run `emplace declined forget` to undo this.".dimmed()
+    );
+
+    Ok(())
+}
+
+/// Print the declined packages.
+pub fn list() -> Result<()> {
+    let declined = Declined::load()?;
+    if declined.packages.is_empty() {
+        println!("No packages have been declined.");
+
+        return Ok(());
+    }
+
+    for package in declined.packages.iter() {
+        println!("- {}", package.color_full_name());
+    }
+
+    Ok(())
+}
+
+/// Forget declined packages so they are asked about again, the user selects them when no names
+/// are passed.
+pub fn forget(names: &[&str]) -> Result<()> {
+    let forgotten = if names.is_empty() {
+        let declined = Declined::load()?;
+        if declined.packages.is_empty() {
+            println!("No packages have been declined.");
+
+            return Ok(());
+        }
+
+        let package_names = declined
+            .packages
+            .iter()
+            .map(|package| package.color_full_name())
+            .collect::<Vec<_>>();
+        let selections = dialoguer::MultiSelect::new()
+            .with_prompt("Select the packages you want to be asked about again (space to select)")
+            .items(&package_names)
+            .interact()
+            .context("failed constructing checkboxes")?;
+
+        let selected = selections
+            .iter()
+            .map(|index| declined.packages[*index].clone())
+            .collect::<Vec<_>>();
+        Declined::modify(|declined| {
+            declined
+                .packages
+                .retain(|package| !selected.contains(package));
+        })?;
+
+        Packages::from(selected)
+    } else {
+        Declined::modify(|declined| declined.forget(names))?
+    };
+
+    for name in names {
+        if !forgotten.iter().any(|package| package.name() == *name) {
+            println!("{} {} wasn't declined.", "Skipping".yellow(), name);
+        }
+    }
+    for package in forgotten.iter() {
+        println!("Forgot {}", package.color_full_name());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Declined;
+    use crate::{
+        package::{Package, Packages},
+        package_manager::{Apt, PackageManager, Pip},
+    };
+
+    #[test]
+    fn test_persist() {
+        let path =
+            std::env::temp_dir().join(format!("emplace-declined-{}.ron", std::process::id()));
+
+        let mut declined = Declined::from_path(&path).unwrap();
+        assert!(declined.packages.is_empty());
+
+        declined.push_packages(&Packages::from(vec![
+            Package::new(PackageManager::from(Apt), "test".to_string(), vec![]),
+            Package::new(PackageManager::from(Pip), "requests".to_string(), vec![]),
+        ]));
+        declined.save().unwrap();
+
+        let mut declined = Declined::from_path(&path).unwrap();
+        assert_eq!(declined.packages.len(), 2);
+
+        // The flags don't matter, the package manager does
+        assert!(declined.contains(&Package::new(
+            PackageManager::from(Apt),
+            "test".to_string(),
+            vec!["--no-install-recommends".to_string()]
+        )));
+        assert!(!declined.contains(&Package::new(
+            PackageManager::from(Pip),
+            "test".to_string(),
+            vec![]
+        )));
+
+        assert_eq!(declined.forget(&["test", "other"]).len(), 1);
+        assert_eq!(declined.packages.len(), 1);
+
+        // An empty list doesn't leave a file behind
+        declined.forget(&["requests"]);
+        declined.save().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,439 @@
+use crate::{
+    config::{Config, StorageKind},
+    configure, git,
+    package_manager::{PackageManager, PackageManagerTrait},
+    queue::Queue,
+    repo::Repo,
+    storage::{StorageBackend, StorageBackendTrait},
+};
+use anyhow::Result;
+use bugreport::{
+    collector::{CollectionError, Collector},
+    report::ReportEntry,
+    CrateInfo,
+};
+use colored::Colorize;
+use itertools::Itertools;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+/// How bad the outcome of a check is.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The outcome of a single check.
+#[derive(Debug)]
+pub struct Check {
+    pub status: Status,
+    pub message: String,
+    /// What the user can do to solve the problem.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    pub fn ok<S: Into<String>>(message: S) -> Self {
+        Self {
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    pub fn warning<S: Into<String>, F: Into<String>>(message: S, fix: F) -> Self {
+        Self {
+            status: Status::Warning,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    pub fn error<S: Into<String>, F: Into<String>>(message: S, fix: F) -> Self {
+        Self {
+            status: Status::Error,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    /// The check without colors, for bug reports.
+    fn plain(&self) -> String {
+        let status = match self.status {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+        };
+
+        match &self.fix {
+            Some(fix) => format!("{}: {} ({})", status, self.message, fix),
+            None => format!("{}: {}", status, self.message),
+        }
+    }
+}
+
+/// Print the outcome of all health checks.
+pub fn doctor<P>(config_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    print_checks(&checks(config_path));
+
+    Ok(())
+}
+
+/// Print the outcome of checks with how to fix the problems.
+pub fn print_checks(checks: &[Check]) {
+    for check in checks {
+        let status = match check.status {
+            Status::Ok => "✓".green(),
+            Status::Warning => "!".yellow(),
+            Status::Error => "✗".red(),
+        };
+        println!("{} {}", status.bold(), check.message);
+
+        if let Some(fix) = &check.fix {
+            println!("  {} {}", "fix:".dimmed(), fix);
+        }
+    }
+}
+
+/// Run all health checks without changing anything.
+pub fn checks<P>(config_path: P) -> Vec<Check>
+where
+    P: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+
+    // Use the settings of all layers combined for the other checks
+    let (config, mut checks) = configure::check_layers(config_path);
+
+    if let Some(config) = config {
+        let storage = StorageBackend::from_config(&config);
+
+        if config.repo.backend == StorageKind::Git {
+            checks.append(&mut check_git(&config, &storage.directory()));
+        }
+        checks.push(check_mirrors(
+            &storage.file_path(),
+            &storage.mirrors_directory(),
+        ));
+        if let Some(check) = check_queue(&config.repo_directory) {
+            checks.push(check);
+        }
+    }
+
+    checks.push(check_package_managers());
+    checks.push(check_shell_hook());
+
+    checks
+}
+
+/// Check whether the repository is set up as configured and in sync with the remote.
+fn check_git(config: &Config, dir: &Path) -> Vec<Check> {
+    let dir_string = dir.display();
+    if !dir.join(".git").exists() {
+        return vec![Check::error(
+            format!(
+                "Repository directory \"{}\" is not a git repository",
+                dir_string
+            ),
+            format!(
+                "run `emplace install` to clone it, or `git clone --branch {} {} {}`",
+                config.repo.branch, config.repo.url, dir_string
+            ),
+        )];
+    }
+
+    let mut checks = vec![Check::ok(format!(
+        "Repository directory \"{}\" is a git repository",
+        dir_string
+    ))];
+
+    if config.repo.url.is_empty() {
+        checks.push(Check::ok(
+            "No remote is configured, the mirrors are only kept on this machine",
+        ));
+
+        return checks;
+    }
+
+    checks.push(match git::remote_url(&dir, &config.repo.transport) {
+        Ok(url) if url == config.repo.url => Check::ok(format!("Remote is \"{}\"", url)),
+        Ok(url) => Check::warning(
+            format!(
+                "Remote is \"{}\" but \"{}\" is configured",
+                url, config.repo.url
+            ),
+            format!(
+                "run `git -C {} remote set-url origin {}`",
+                dir_string, config.repo.url
+            ),
+        ),
+        Err(_) => Check::error(
+            "Repository has no origin remote",
+            format!(
+                "run `git -C {} remote add origin {}`",
+                dir_string, config.repo.url
+            ),
+        ),
+    });
+
+    checks.push(match git::current_branch(&dir, &config.repo.transport) {
+        Ok(branch) if branch == config.repo.branch => {
+            Check::ok(format!("Branch \"{}\" is checked out", branch))
+        }
+        Ok(branch) => Check::warning(
+            format!(
+                "Branch \"{}\" is checked out but \"{}\" is configured",
+                branch, config.repo.branch
+            ),
+            format!(
+                "run `git -C {} checkout {}`",
+                dir_string, config.repo.branch
+            ),
+        ),
+        Err(err) => Check::error(
+            format!("Current branch can't be determined: {}", err),
+            format!("run `git -C {} status` to see what's wrong", dir_string),
+        ),
+    });
+
+    checks.push(match git::ahead_behind(&dir, &config.repo.transport) {
+        Ok((0, 0)) => Check::ok("Branch is up to date with its upstream"),
+        Ok((0, behind)) => Check::ok(format!(
+            "Branch is {} commits behind its upstream, they will be pulled",
+            behind
+        )),
+        Ok((ahead, 0)) => Check::warning(
+            format!("{} commits haven't been pushed", ahead),
+            "run `emplace sync`",
+        ),
+        Ok((ahead, behind)) => Check::error(
+            format!(
+                "Branch has diverged, {} local and {} remote commits",
+                ahead, behind
+            ),
+            "run `emplace sync --rebase` or resolve it manually",
+        ),
+        Err(_) => Check::warning(
+            "Branch doesn't track an upstream branch",
+            format!(
+                "run `git -C {} branch --set-upstream-to=origin/{}`",
+                dir_string, config.repo.branch
+            ),
+        ),
+    });
+
+    checks
+}
+
+/// Check whether the mirrors can be read and contain no duplicates.
+fn check_mirrors(file: &Path, directory: &Path) -> Check {
+    let location = if directory.exists() { directory } else { file };
+
+    let packages = match Repo::read_path(file, directory) {
+        Ok(packages) => packages,
+        Err(err) => {
+            return Check::error(
+                format!(
+                    "Mirrors \"{}\" can't be read: {:?}",
+                    location.display(),
+                    err
+                ),
+                "fix the file by hand, undo the last change with `emplace undo` or mirror a package to create it",
+            )
+        }
+    };
+
+    let duplicates = packages
+        .iter()
+        .duplicates_by(|package| (package.source().full_name(), package.full_command()))
+        .map(|package| package.full_command())
+        .collect::<Vec<_>>();
+    if !duplicates.is_empty() {
+        return Check::warning(
+            format!(
+                "Mirrors \"{}\" contain duplicates: {}",
+                location.display(),
+                duplicates.join(", ")
+            ),
+            "mirroring a new package removes them",
+        );
+    }
+
+    Check::ok(format!(
+        "Mirrors \"{}\" contain {} packages",
+        location.display(),
+        packages.len()
+    ))
+}
+
+/// Check which package managers are available on this machine.
+fn check_package_managers() -> Check {
+    let available = PackageManager::iter()
+        .filter(|manager| manager.is_available())
+        .map(|manager| manager.full_name())
+        .collect::<Vec<_>>();
+
+    if available.is_empty() {
+        Check::warning(
+            "No supported package managers are available",
+            "make sure the package managers are in your PATH",
+        )
+    } else {
+        Check::ok(format!(
+            "Available package managers: {}",
+            available.join(", ")
+        ))
+    }
+}
+
+/// Check whether the shell runs `emplace init`.
+fn check_shell_hook() -> Check {
+    // The hooks export the config path
+    if env::var_os("EMPLACE_CONFIG").is_some() {
+        return Check::ok("Shell hook is active");
+    }
+
+    let files = shell_config_files()
+        .into_iter()
+        .filter(|file| {
+            fs::read_to_string(file)
+                .map(|contents| contents.contains("emplace init"))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    if !files.is_empty() {
+        return Check::warning(
+            format!(
+                "Shell hook is installed in {} but not active",
+                files.iter().map(|file| file.display()).join(", ")
+            ),
+            "restart your shell",
+        );
+    }
+
+    let shell = env::var("SHELL")
+        .ok()
+        .and_then(|shell| shell.rsplit('/').next().map(|name| name.to_string()))
+        .unwrap_or_else(|| "bash".to_string());
+    Check::warning(
+        "Shell hook is not installed, commands aren't caught",
+        format!(
+            "add the output of `emplace init {}` to your shell's config file",
+            shell
+        ),
+    )
+}
+
+/// The files where the shell hook is usually installed.
+fn shell_config_files() -> Vec<PathBuf> {
+    let home = dirs::home_dir().unwrap_or_default();
+    let config = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+
+    vec![
+        home.join(".bashrc"),
+        home.join(".bash_profile"),
+        home.join(".zshrc"),
+        config.join("fish").join("config.fish"),
+        config.join("nushell").join("config.nu"),
+    ]
+}
+
+/// Check whether anything is waiting to be shared.
+fn check_queue(dir: &str) -> Option<Check> {
+    let queue = match Queue::load(dir) {
+        Ok(queue) => queue,
+        Err(err) => {
+            return Some(Check::error(
+                format!("Queue can't be read: {}", err),
+                format!("remove \"{}\"", Queue::path_for(dir)),
+            ))
+        }
+    };
+
+    if let Some(error) = &queue.error {
+        Some(Check::warning(
+            format!(
+                "Mirroring in the background failed: {}",
+                error.lines().next().unwrap_or_default()
+            ),
+            "run `emplace sync` to see the full error",
+        ))
+    } else if !queue.is_empty() {
+        Some(Check::warning(
+            format!("{} mirrored items are pending", queue.len()),
+            "run `emplace sync`",
+        ))
+    } else {
+        None
+    }
+}
+
+/// Add the health checks to bug reports.
+pub struct DoctorCollector {
+    config_path: PathBuf,
+}
+
+impl DoctorCollector {
+    /// Instantiate.
+    pub fn new<P: AsRef<Path>>(config_path: P) -> Self {
+        Self {
+            config_path: config_path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Collector for DoctorCollector {
+    fn description(&self) -> &str {
+        "Emplace doctor"
+    }
+
+    fn collect(&mut self, _: &CrateInfo) -> std::result::Result<ReportEntry, CollectionError> {
+        Ok(ReportEntry::List(
+            checks(&self.config_path)
+                .iter()
+                .map(|check| ReportEntry::Text(check.plain()))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirrors() {
+        let path = std::env::temp_dir().join(format!("emplace-doctor-{}.ron", std::process::id()));
+        let directory = path.with_extension("d");
+
+        fs::write(
+            &path,
+            r#"([(source: Cargo, name: "foo"), (source: Cargo, name: "foo")])"#,
+        )
+        .unwrap();
+        let check = check_mirrors(&path, &directory);
+        assert_eq!(check.status, Status::Warning);
+        assert!(check.message.contains("foo"));
+
+        fs::write(
+            &path,
+            r#"([(source: Cargo, name: "foo"), (source: Pip, name: "foo")])"#,
+        )
+        .unwrap();
+        assert_eq!(check_mirrors(&path, &directory).status, Status::Ok);
+
+        fs::write(&path, "not ron").unwrap();
+        assert_eq!(check_mirrors(&path, &directory).status, Status::Error);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(check_mirrors(&path, &directory).status, Status::Error);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    alias::Aliases,
+    package::{self, LocalFile, Packages, ParseResult},
+    package_manager::{ArgumentKind, PackageManager, PackageManagerTrait, Token},
+};
+use anyhow::Result;
+use colored::Colorize;
+use std::path::Path;
+
+/// Print how a line is parsed into packages.
+pub fn explain<P>(line: &str, aliases_path: Option<P>) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    // Replace the aliases with the commands they point to, like catch does
+    let line = match aliases_path {
+        Some(aliases_path) => {
+            let expanded = Aliases::from_path(aliases_path)?.expand(line);
+            if expanded != line {
+                println!("{} {}", "Expanded aliases:".bold(), expanded);
+            }
+
+            expanded
+        }
+        None => line.to_string(),
+    };
+
+    for (index, command) in package::split_commands(&line)
+        .filter(|command| !command.trim().is_empty())
+        .enumerate()
+    {
+        println!("{} {}: \"{}\"", "Command".bold(), index + 1, command.trim());
+
+        let managers: Vec<PackageManager> = PackageManager::from_line_iter(command).collect();
+        if managers.is_empty() {
+            println!("  {}", "No package manager matches".dimmed());
+            continue;
+        }
+
+        for manager in managers {
+            println!("  {}", manager.full_name().green());
+
+            for parse in manager.parse(command) {
+                println!("    {} {}", "command:".dimmed(), parse.command);
+
+                let sub_command = match parse.sub_command {
+                    Some(sub_command) => sub_command,
+                    None => {
+                        println!(
+                            "    {} none of {}, nothing is caught",
+                            "subcommand:".dimmed(),
+                            manager.sub_commands().join(", ")
+                        );
+                        continue;
+                    }
+                };
+                println!("    {} {}", "subcommand:".dimmed(), sub_command);
+
+                for (value, token) in parse.tokens.iter() {
+                    println!("    {:<30} {}", value, describe_token(*token));
+                }
+
+                if !parse.flags.is_empty() {
+                    println!(
+                        "    {} {}",
+                        "caught flags:".dimmed(),
+                        parse.flags.join(", ")
+                    );
+                }
+            }
+        }
+    }
+
+    let ParseResult { packages, warnings } = Packages::parse_line(&line);
+    println!("{}", "Packages:".bold());
+    if packages.is_empty() {
+        println!("  {}", "None".dimmed());
+    }
+    for package in packages.iter() {
+        println!("- {}", package.color_full_name());
+    }
+
+    if !warnings.is_empty() {
+        println!("{}", "Warnings:".bold());
+        for warning in warnings {
+            println!("- {}", warning.to_string().yellow());
+        }
+    }
+
+    let local_files = LocalFile::from_line(&line);
+    if !local_files.is_empty() {
+        println!("{}", "Files on this machine:".bold());
+        for file in local_files {
+            println!("- {}", file.color_full_name());
+        }
+    }
+
+    Ok(())
+}
+
+/// A human readable description of how a token is interpreted.
+fn describe_token(token: Token) -> String {
+    match token {
+        Token::Argument(ArgumentKind::Package) => "package".green().to_string(),
+        Token::Argument(ArgumentKind::Url) => "package from URL".green().to_string(),
+        Token::Argument(ArgumentKind::LocalPath) => "file on this machine".yellow().to_string(),
+        Token::Argument(ArgumentKind::RequirementsFile) => "requirements file".yellow().to_string(),
+        Token::Skipped => "skipped, doesn't look like a package".dimmed().to_string(),
+        Token::Flag => "ignored flag".dimmed().to_string(),
+        Token::FlagValue => "skipped flag value".dimmed().to_string(),
+        Token::CapturedFlag => "captured flag".cyan().to_string(),
+        Token::PathFlag => "flag with a file as value".cyan().to_string(),
+        Token::InvalidatingFlag => "invalidating flag, nothing is caught".red().to_string(),
+    }
+}
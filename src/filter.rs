@@ -0,0 +1,218 @@
+use crate::{
+    package::Package,
+    package_manager::{PackageManager, PackageManagerTrait},
+};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt};
+
+/// A package name, glob pattern like `linux-headers-*` or regular expression like `/^python3?-/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern {
+    /// The pattern as it's written in the config.
+    source: String,
+    /// The compiled pattern, nothing when the name must match exactly.
+    regex: Option<Regex>,
+}
+
+impl Pattern {
+    /// Whether the name matches this pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(name),
+            None => self.source == name,
+        }
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let regex = if let Some(regex) = source
+            .strip_prefix('/')
+            .and_then(|source| source.strip_suffix('/'))
+        {
+            // Regular expressions match anywhere in the name unless they are anchored
+            Some(Regex::new(regex).map_err(|err| anyhow!("invalid pattern {}: {}", source, err))?)
+        } else if source.contains(['*', '?']) {
+            Some(glob_regex(&source))
+        } else {
+            None
+        };
+
+        Ok(Self { source, regex })
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.source
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// A property of a package that it can be selected by.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tag {
+    /// Installing the package needs administrative permissions.
+    Root,
+    /// The package is installed without administrative permissions.
+    User,
+    /// A package file copied into the repository.
+    Artifact,
+}
+
+impl Tag {
+    /// Whether the package has this tag.
+    pub fn matches(self, package: &Package) -> bool {
+        match self {
+            Tag::Root => package.source().needs_root(),
+            Tag::User => !package.source().needs_root(),
+            Tag::Artifact => package.is_artifact(),
+        }
+    }
+}
+
+/// Packages selected by their package manager, name or tags.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PackageFilter {
+    /// Select every package of these package managers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "manager_names")]
+    pub managers: Vec<PackageManager>,
+    /// Select the packages with a name matching one of these patterns.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<Pattern>,
+    /// Select the packages with one of these tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+}
+
+impl PackageFilter {
+    /// Whether the package is selected by this filter.
+    pub fn matches(&self, package: &Package) -> bool {
+        self.managers.contains(&package.source())
+            || self.packages.iter().any(|pattern| {
+                // Package files can be matched by their path or the name of the package
+                pattern.matches(package.name()) || pattern.matches(package.installed_name())
+            })
+            || self.tags.iter().any(|tag| tag.matches(package))
+    }
+
+    /// Whether nothing is selected by this filter.
+    pub fn is_empty(&self) -> bool {
+        self.managers.is_empty() && self.packages.is_empty() && self.tags.is_empty()
+    }
+}
+
+/// Package managers written by their name instead of how they are stored in the mirrors.
+mod manager_names {
+    use crate::package_manager::PackageManager;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(managers: &[PackageManager], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            managers
+                .iter()
+                .map(|manager| -> &'static str { manager.into() }),
+        )
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<PackageManager>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|name| {
+                PackageManager::from_name(name)
+                    .ok_or_else(|| D::Error::custom(format!("unknown package manager {}", name)))
+            })
+            .collect()
+    }
+}
+
+/// Convert a glob pattern where `*` matches any text and `?` a single character into a regular
+/// expression matching the whole name.
+fn glob_regex(glob: &str) -> Regex {
+    let regex = glob
+        .split('*')
+        .map(|part| {
+            part.split('?')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect::<Vec<_>>()
+        .join(".*");
+
+    Regex::new(&format!("^{}$", regex)).expect("Escaped glob is always a valid regex")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PackageFilter, Pattern};
+    use crate::{
+        package::Package,
+        package_manager::{Apt, CargoBinstall, PackageManager, Pip},
+    };
+    use std::convert::TryFrom;
+
+    fn pattern(source: &str) -> Pattern {
+        Pattern::try_from(source.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert!(pattern("fzf").matches("fzf"));
+        assert!(!pattern("fzf").matches("fzf-tmux"));
+
+        assert!(pattern("linux-headers-*").matches("linux-headers-6.1.0"));
+        assert!(!pattern("linux-headers-*").matches("linux-image-6.1.0"));
+        assert!(pattern("python?").matches("python3"));
+        assert!(!pattern("python?").matches("python"));
+        assert!(pattern("lib.*").matches("lib.*"));
+        assert!(!pattern("lib*.so").matches("libxso"));
+
+        assert!(pattern("/^python3?-/").matches("python3-dev"));
+        assert!(!pattern("/^python3?-/").matches("ipython3-dev"));
+        assert!(Pattern::try_from("/(/".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter: PackageFilter = toml::from_str(indoc::indoc! {r#"
+            managers = ["Pip", "cargo-binstall"]
+            packages = ["linux-headers-*"]
+        "#})
+        .unwrap();
+        assert!(toml::from_str::<PackageFilter>("managers = [\"apt-get\"]").is_err());
+
+        let package =
+            |source: PackageManager, name: &str| Package::new(source, name.to_string(), vec![]);
+        assert!(filter.matches(&package(Pip.into(), "requests")));
+        assert!(filter.matches(&package(CargoBinstall.into(), "ripgrep")));
+        assert!(filter.matches(&package(Apt.into(), "linux-headers-6.1.0")));
+        assert!(!filter.matches(&package(Apt.into(), "fzf")));
+        assert!(PackageFilter::default().is_empty());
+
+        let filter: PackageFilter = toml::from_str("tags = [\"artifact\"]").unwrap();
+        assert!(filter.matches(&Package::artifact(
+            Apt.into(),
+            "artifacts/foo_1.0_amd64.deb".to_string(),
+            vec![]
+        )));
+        assert!(!filter.matches(&package(Apt.into(), "foo")));
+    }
+}
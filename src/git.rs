@@ -1,20 +1,168 @@
+use crate::config::{Identity, SigningConfig, TransportConfig};
 use anyhow::{anyhow, Context, Result};
 use itertools::Itertools;
-use log::{debug, error};
+use log::debug;
 use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::{Command, Output},
     str,
 };
 
-fn build_command<P: AsRef<Path>>(command: Vec<&str>, path: &P) -> Result<Command> {
+/// The reason a git command failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GitErrorKind {
+    /// The remote refused the credentials.
+    Auth,
+    /// A local file or directory can't be accessed.
+    Permission,
+    /// The remote couldn't be reached.
+    Network,
+    /// The local and remote history can't be combined automatically.
+    Conflict,
+    /// The configured branch doesn't exist on the remote.
+    MissingBranch,
+    /// The URL doesn't point to a git repository.
+    BadRemote,
+    /// Anything else.
+    Other,
+}
+
+impl GitErrorKind {
+    /// Guess the reason from the output of git.
+    fn from_output(output: &str) -> Self {
+        let output = output.to_lowercase();
+        let contains_any = |patterns: &[&str]| patterns.iter().any(|p| output.contains(p));
+
+        if contains_any(&[
+            "authentication failed",
+            "permission denied (publickey",
+            "could not read username",
+            "could not read password",
+            "terminal prompts disabled",
+            "invalid username or password",
+        ]) {
+            GitErrorKind::Auth
+        } else if contains_any(&["permission denied", "insufficient permission"]) {
+            GitErrorKind::Permission
+        } else if contains_any(&[
+            "couldn't find remote ref",
+            "remote branch",
+            "unknown revision",
+        ]) {
+            GitErrorKind::MissingBranch
+        } else if contains_any(&[
+            "does not appear to be a git repository",
+            "repository not found",
+        ]) || (output.contains("fatal: repository '") && output.contains("' not found"))
+        {
+            // Checked before the network errors, git also can't read from the remote then
+            GitErrorKind::BadRemote
+        } else if contains_any(&[
+            "could not resolve host",
+            "unable to access",
+            "connection refused",
+            "connection timed out",
+            "network is unreachable",
+            "could not read from remote repository",
+        ]) {
+            GitErrorKind::Network
+        } else if contains_any(&[
+            "conflict",
+            "non-fast-forward",
+            "[rejected]",
+            "fetch first",
+            "divergent branches",
+            "automatic merge failed",
+            "not possible to fast-forward",
+        ]) {
+            GitErrorKind::Conflict
+        } else {
+            GitErrorKind::Other
+        }
+    }
+
+    /// What the user can do to solve the problem.
+    pub fn hint(self) -> &'static str {
+        match self {
+            GitErrorKind::Auth => "check that your SSH key or credential helper has access to the repository URL in the config",
+            GitErrorKind::Permission => "check that your user owns the repository directory and can write to it",
+            GitErrorKind::Network => "check your connection, commits that couldn't be pushed can be shared later with `emplace sync`",
+            GitErrorKind::Conflict => "the local and remote history diverged, resolve it manually in the repository directory or try `emplace sync --rebase`",
+            GitErrorKind::MissingBranch => "the branch in the config doesn't exist on the remote, change it in the config or push it first",
+            GitErrorKind::BadRemote => "check the repository URL in the config, it doesn't point to a git repository",
+            GitErrorKind::Other => "run the command manually in the repository directory to see what's wrong",
+        }
+    }
+}
+
+/// A git command that didn't succeed.
+#[derive(Debug)]
+pub struct GitError {
+    /// Why the command failed.
+    pub kind: GitErrorKind,
+    /// The command that failed.
+    command: String,
+    /// Exit code of the command, if it wasn't killed by a signal.
+    status: Option<i32>,
+    /// What git printed on stderr.
+    stderr: String,
+}
+
+impl GitError {
+    /// Construct the error from a failed command.
+    fn new(command: String, output: &Output) -> Self {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        Self {
+            kind: GitErrorKind::from_output(&format!("{}\n{}", stderr, stdout)),
+            command,
+            status: output.status.code(),
+            stderr,
+        }
+    }
+
+    /// Get the kind of the git error anywhere in the chain of an error.
+    pub fn kind_of(err: &anyhow::Error) -> Option<GitErrorKind> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<GitError>())
+            .map(|git_err| git_err.kind)
+    }
+}
+
+impl Display for GitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(f, "\"{}\" exited with status {}", self.command, status)?,
+            None => write!(f, "\"{}\" was terminated", self.command)?,
+        }
+        if !self.stderr.is_empty() {
+            write!(f, ":\n{}", self.stderr)?;
+        }
+
+        write!(f, "\nhint: {}", self.kind.hint())
+    }
+}
+
+impl Error for GitError {}
+
+fn build_command<P: AsRef<Path>>(
+    command: &[&str],
+    path: &P,
+    transport: &TransportConfig,
+) -> Result<Command> {
     let mut iter = command.iter();
     let cmd_name = iter
         .next()
         .ok_or_else(|| anyhow!("Malformed git command"))?;
 
-    let mut cmd = Command::new(cmd_name);
-    cmd.stderr(Stdio::null());
+    let mut cmd = if *cmd_name == "git" {
+        git_command(transport)
+    } else {
+        Command::new(cmd_name)
+    };
     cmd.current_dir(path);
 
     debug!(
@@ -32,37 +180,105 @@ fn build_command<P: AsRef<Path>>(command: Vec<&str>, path: &P) -> Result<Command
     Ok(cmd)
 }
 
-fn call_on_path<P: AsRef<Path>>(command: Vec<&str>, path: &P) -> Result<bool> {
-    let mut cmd = build_command(command, path)?;
-    cmd.stdout(Stdio::null());
+/// The git command with the configured transport settings applied.
+fn git_command(transport: &TransportConfig) -> Command {
+    let mut cmd = Command::new(transport.git.as_deref().unwrap_or("git"));
+    for option in transport.config.iter() {
+        cmd.args(["-c", option]);
+    }
+    if let Some(ssh_command) = transport.git_ssh_command() {
+        cmd.env("GIT_SSH_COMMAND", ssh_command);
+    }
+    cmd.envs(transport.env.iter());
+
+    cmd
+}
 
-    let mut child = cmd.spawn().context("failed spawning process")?;
-    let result = child.wait().context("failed waiting for result")?;
+/// Run the command, returning an error with what git printed when it fails.
+fn call_on_path<P: AsRef<Path>>(
+    command: Vec<&str>,
+    path: &P,
+    transport: &TransportConfig,
+) -> Result<Output> {
+    let output = build_command(&command, path, transport)?
+        .output()
+        .context("failed spawning process")?;
 
-    Ok(result.success())
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let command = command.iter().filter(|arg| !arg.is_empty()).join(" ");
+
+        Err(GitError::new(command, &output).into())
+    }
 }
 
-fn call_on_path_has_output<P: AsRef<Path>>(command: Vec<&str>, path: &P) -> Result<bool> {
-    let mut cmd = build_command(command, path)?;
+fn call_on_path_has_output<P: AsRef<Path>>(
+    command: Vec<&str>,
+    path: &P,
+    transport: &TransportConfig,
+) -> Result<bool> {
+    let output = call_on_path(command, path, transport)?;
 
-    Ok(str::from_utf8(&cmd.output()?.stdout)?.trim() != "")
+    Ok(str::from_utf8(&output.stdout)?.trim() != "")
 }
 
-pub fn commit_all<P: AsRef<Path>>(dir: &P, msg: &str, sign: bool) -> Result<bool> {
+/// Commit all staged changes, returns false when there's nothing to commit.
+pub fn commit_all<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    msg: &str,
+    signing: &SigningConfig,
+    author: Option<&Identity>,
+) -> Result<bool> {
+    // Committing without changes fails, which isn't a problem
+    if call_on_path(vec!["git", "diff", "--cached", "--quiet"], dir, transport).is_ok() {
+        return Ok(false);
+    }
+
+    let format = format!("gpg.format={}", signing.format.git_format());
+    let sign = match &signing.key {
+        Some(key) => format!("-S{}", key),
+        None => "-S".to_string(),
+    };
+
+    let (name, email) = match author {
+        Some(author) => (
+            format!("user.name={}", author.name),
+            format!("user.email={}", author.email),
+        ),
+        None => Default::default(),
+    };
+
+    let mut command = vec!["git"];
+    if signing.sign {
+        command.extend(["-c", &format]);
+    }
+    if author.is_some() {
+        // Used for both the author and the committer
+        command.extend(["-c", &name, "-c", &email]);
+    }
+    command.extend(["commit", if signing.sign { &sign } else { "" }, "-am", msg]);
+
+    call_on_path(command, dir, transport).context("failed committing everything in git")?;
+
+    Ok(true)
+}
+
+/// Push the branch to the origin remote, tracking it when that's not done yet.
+pub fn push<P: AsRef<Path>>(dir: &P, transport: &TransportConfig, branch: &str) -> Result<()> {
     call_on_path(
-        vec!["git", "commit", if sign { "-S" } else { "" }, "-am", msg],
+        vec!["git", "push", "--set-upstream", "origin", branch],
         dir,
+        transport,
     )
-    .context("failed committing everything in git")
-}
+    .context("failed pushing in git")?;
 
-pub fn push<P: AsRef<Path>>(dir: &P) -> Result<bool> {
-    call_on_path(vec!["git", "push"], dir).context("failed pushing in git")
+    Ok(())
 }
 
-/// Perform a pull on the git repo by fetching & merging.
-pub fn pull<P: AsRef<Path>>(dir: &P, branch: &str) -> Result<bool> {
-    // First fetch the remote changes
+/// Fetch the remote changes of a branch without applying them.
+pub fn fetch<P: AsRef<Path>>(dir: &P, transport: &TransportConfig, branch: &str) -> Result<()> {
     call_on_path(
         vec![
             "git",
@@ -75,78 +291,695 @@ pub fn pull<P: AsRef<Path>>(dir: &P, branch: &str) -> Result<bool> {
             branch,
         ],
         dir,
+        transport,
     )
     .context("failed pulling in git: fetch")?;
-    // Then merge them into this branch
+
+    Ok(())
+}
+
+/// Merge the fetched remote changes into this branch.
+pub fn merge<P: AsRef<Path>>(dir: &P, transport: &TransportConfig, branch: &str) -> Result<()> {
+    // Repositories that aren't cloned don't know `origin` refers to the remote branch
+    let upstream = format!("origin/{}", branch);
     call_on_path(
+        vec!["git", "merge", "--strategy-option", "theirs", &upstream],
+        dir,
+        transport,
+    )
+    .context("failed pulling in git: merge")?;
+
+    Ok(())
+}
+
+/// Rebase the local commits on top of the fetched remote changes.
+pub fn rebase<P: AsRef<Path>>(dir: &P, transport: &TransportConfig, branch: &str) -> Result<()> {
+    let upstream = format!("origin/{}", branch);
+    call_on_path(vec!["git", "rebase", &upstream], dir, transport)
+        .context("failed pulling in git: rebase")?;
+
+    Ok(())
+}
+
+/// A commit with the signature information git could find.
+#[derive(Debug)]
+pub struct Signature {
+    /// Hash of the commit.
+    pub commit: String,
+    /// Signature status as reported by git's `%G?` format, 'G' and 'U' are valid signatures.
+    pub status: char,
+    /// Fingerprint of the key that signed the commit.
+    pub fingerprint: String,
+    /// ID of the key that signed the commit.
+    pub key: String,
+}
+
+impl Signature {
+    /// Whether the commit has a valid signature from one of the allowed keys.
+    pub fn is_signed_by(&self, allowed_keys: &[String]) -> bool {
+        if !matches!(self.status, 'G' | 'U') {
+            return false;
+        }
+
+        allowed_keys
+            .iter()
+            .map(|allowed| allowed.trim())
+            .filter(|allowed| !allowed.is_empty())
+            .any(|allowed| {
+                if allowed.chars().all(|c| c.is_ascii_hexdigit()) {
+                    // GPG fingerprints & IDs are hexadecimal, short IDs are the end of the fingerprint
+                    let allowed = allowed.to_uppercase();
+
+                    self.fingerprint.to_uppercase().ends_with(&allowed)
+                        || self.key.to_uppercase() == allowed
+                } else {
+                    // SSH fingerprints are base64 so the case matters
+                    self.fingerprint == allowed || self.key == allowed
+                }
+            })
+    }
+}
+
+/// A commit from the history of a file.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    /// Hash of the commit.
+    pub hash: String,
+    /// Name of the author.
+    pub author: String,
+    /// When it was authored.
+    pub date: String,
+    /// First line of the commit message.
+    pub subject: String,
+    /// The value of the `Emplace-Host` trailer, if the commit has one.
+    pub host: Option<String>,
+}
+
+impl Commit {
+    /// Whether the commit is made by emplace instead of by hand.
+    pub fn is_emplace(&self) -> bool {
+        self.host.is_some() || self.subject.starts_with("Emplace - ")
+    }
+}
+
+/// Get the commits that changed any of the files, oldest first.
+pub fn file_history<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    files: &[&str],
+) -> Result<Vec<Commit>> {
+    let mut command = vec![
+        "git",
+        "log",
+        "--reverse",
+        // Commits of merged histories are part of the merge commit
+        "--first-parent",
+        "--date=format:%Y-%m-%d %H:%M",
+        // Separate the fields & commits with characters that can't be in the message
+        "--format=%H%x1f%an%x1f%ad%x1f%s%x1f%(trailers:key=Emplace-Host,valueonly,separator=%x2C)%x1e",
+        "--",
+    ];
+    command.extend(files);
+    let output =
+        call_on_path(command, dir, transport).context("failed reading file history in git")?;
+
+    Ok(str::from_utf8(&output.stdout)?
+        .split('\x1e')
+        .filter_map(|commit| {
+            let mut fields = commit.trim().split('\x1f');
+
+            Some(Commit {
+                hash: fields.next().filter(|hash| !hash.is_empty())?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+                host: fields
+                    .next()
+                    .map(|host| host.trim().to_string())
+                    .filter(|host| !host.is_empty()),
+            })
+        })
+        .collect())
+}
+
+/// Get the contents of a file at a commit, nothing when it doesn't exist there.
+pub fn show_file<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    hash: &str,
+    file: &str,
+) -> Result<Option<String>> {
+    let listed = call_on_path(
+        vec!["git", "ls-tree", "--name-only", hash, "--", file],
+        dir,
+        transport,
+    )
+    .context("failed listing file in git")?;
+    if listed.stdout.is_empty() {
+        return Ok(None);
+    }
+
+    let object = format!("{}:{}", hash, file);
+    let output = call_on_path(vec!["git", "show", &object], dir, transport)
+        .context("failed showing file in git")?;
+
+    Ok(Some(String::from_utf8(output.stdout)?))
+}
+
+/// Get the paths of all files in a directory at a commit.
+pub fn list_files<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    hash: &str,
+    directory: &str,
+) -> Result<Vec<String>> {
+    let output = call_on_path(
+        vec!["git", "ls-tree", "-r", "--name-only", hash, "--", directory],
+        dir,
+        transport,
+    )
+    .context("failed listing files in git")?;
+
+    Ok(str::from_utf8(&output.stdout)?
+        .lines()
+        .map(|file| file.to_string())
+        .collect())
+}
+
+/// Get the signatures of the commits in a revision range like `HEAD..origin/master`.
+pub fn signatures<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    range: &str,
+    allowed_signers_file: Option<&str>,
+) -> Result<Vec<Signature>> {
+    let allowed_signers = allowed_signers_file
+        .map(|file| format!("gpg.ssh.allowedSignersFile={}", file))
+        .unwrap_or_default();
+
+    let output = call_on_path(
         vec![
             "git",
-            "merge",
-            "--strategy-option",
-            "theirs",
-            "origin",
-            branch,
+            if allowed_signers.is_empty() { "" } else { "-c" },
+            &allowed_signers,
+            "log",
+            "--format=%H%x09%G?%x09%GF%x09%GK",
+            range,
         ],
         dir,
+        transport,
     )
-    .context("failed pulling in git: merge")
+    .context("failed reading commit signatures in git")?;
+
+    Ok(str::from_utf8(&output.stdout)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+
+            Some(Signature {
+                commit: fields.next()?.to_string(),
+                status: fields.next()?.chars().next()?,
+                fingerprint: fields.next().unwrap_or_default().to_string(),
+                key: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
 }
 
 /// Clone a full repository.
-pub fn clone_full(dir: &str, url: &str) -> Result<bool> {
+pub fn clone_full(
+    dir: &str,
+    transport: &TransportConfig,
+    url: &str,
+    branch: &str,
+    depth: Option<u32>,
+    sparse: Option<&[String]>,
+) -> Result<()> {
+    let options = clone_options(depth, sparse);
+
+    let mut command = vec!["git", "clone"];
+    command.extend(options.iter().map(|option| option.as_str()));
+    command.extend([url, dir]);
+
     // Create a dummy path since we specify absolute path for cloning
     let dummy_path = PathBuf::from("./");
-    call_on_path(vec!["git", "clone", url, dir], &dummy_path)
+    call_on_path(command, &dummy_path, transport).context("failed cloning repository in git")?;
+
+    if let Some(patterns) = sparse {
+        sparse_checkout(&dir, transport, branch, patterns)?;
+    }
+
+    Ok(())
 }
 
-pub fn clone_single_branch<P: AsRef<Path>>(dir: &P, url: &str, branch: &str) -> Result<bool> {
-    let success = call_on_path(
-        vec![
-            "git",
-            "clone",
-            "--single-branch",
-            "--branch",
+pub fn clone_single_branch<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    url: &str,
+    branch: &str,
+    depth: Option<u32>,
+    sparse: Option<&[String]>,
+) -> Result<()> {
+    let options = clone_options(depth, sparse);
+
+    let mut command = vec!["git", "clone", "--single-branch", "--branch", branch];
+    command.extend(options.iter().map(|option| option.as_str()));
+    command.extend([url, "."]);
+
+    call_on_path(command, dir, transport).with_context(|| {
+        format!(
+            "failed cloning branch in git, to clone it manually execute:\n\n\tgit clone --single-branch --branch {} {} {} {}\n",
             branch,
+            options.join(" "),
             url,
-            ".",
-        ],
-        dir,
-    )
-    .context("failed cloning branch in git")?;
+            dir.as_ref().display()
+        )
+    })?;
+
+    if let Some(patterns) = sparse {
+        sparse_checkout(dir, transport, branch, patterns)?;
+    }
 
-    if !success {
-        // The git process reported that it succeeded but no clone has been done successfully
-        error!("Cloning git branch failed, please execute the following code manually:\n\n\tgit clone --single-branch --branch {} {} {}\n", branch, url, dir.as_ref().to_str().unwrap())
+    Ok(())
+}
+
+/// Extra arguments for cloning only the latest commits or only the files that are checked out.
+fn clone_options(depth: Option<u32>, sparse: Option<&[String]>) -> Vec<String> {
+    let mut options = vec![];
+    if let Some(depth) = depth {
+        options.push(format!("--depth={}", depth));
+    }
+    if sparse.is_some() {
+        // Only download the contents of the files when they are checked out
+        options.push("--filter=blob:none".to_string());
+        options.push("--no-checkout".to_string());
     }
 
-    Ok(success)
+    options
 }
+
+/// Only check out the files matching the patterns.
+fn sparse_checkout<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    branch: &str,
+    patterns: &[String],
+) -> Result<()> {
+    let mut command = vec!["git", "sparse-checkout", "set", "--no-cone"];
+    command.extend(patterns.iter().map(|pattern| pattern.as_str()));
+    call_on_path(command, dir, transport).context("failed setting sparse checkout in git")?;
+
+    call_on_path(vec!["git", "checkout", branch], dir, transport)
+        .context("failed checking out sparse branch in git")?;
+
+    Ok(())
+}
+
+/// Drop the history beyond the latest commits of the remote branch.
+pub fn shorten_history<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    branch: &str,
+    depth: u32,
+) -> Result<()> {
+    let depth = format!("--depth={}", depth);
+    call_on_path(
+        vec!["git", "fetch", "--no-tags", &depth, "origin", branch],
+        dir,
+        transport,
+    )
+    .context("failed shortening history in git")?;
+
+    Ok(())
+}
+
 /// Set remote origin
-pub fn set_remote<P: AsRef<Path>>(dir: &P, remote: &str) -> Result<bool> {
-    call_on_path(vec!["git", "remote", "add", "origin", remote], dir)
-        .context("failed setting remote origin")
+pub fn set_remote<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    remote: &str,
+) -> Result<()> {
+    call_on_path(
+        vec!["git", "remote", "add", "origin", remote],
+        dir,
+        transport,
+    )
+    .context("failed setting remote origin")?;
+
+    Ok(())
+}
+
+/// Change the URL of the origin remote.
+pub fn set_remote_url<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    remote: &str,
+) -> Result<()> {
+    call_on_path(
+        vec!["git", "remote", "set-url", "origin", remote],
+        dir,
+        transport,
+    )
+    .context("failed changing remote origin")?;
+
+    Ok(())
+}
+
+/// Whether the history beyond some commits isn't available because the clone is shallow.
+pub fn is_shallow<P: AsRef<Path>>(dir: &P, transport: &TransportConfig) -> Result<bool> {
+    let output = call_on_path(
+        vec!["git", "rev-parse", "--is-shallow-repository"],
+        dir,
+        transport,
+    )
+    .context("failed checking for shallow clone in git")?;
+
+    Ok(str::from_utf8(&output.stdout)?.trim() == "true")
+}
+
+/// Whether the checked out branch has any commits.
+pub fn has_commits<P: AsRef<Path>>(dir: &P, transport: &TransportConfig) -> bool {
+    call_on_path(
+        vec!["git", "rev-parse", "--verify", "--quiet", "HEAD"],
+        dir,
+        transport,
+    )
+    .is_ok()
+}
+
+/// Merge a fetched remote branch that doesn't share any history with this branch.
+pub fn merge_unrelated<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    branch: &str,
+) -> Result<()> {
+    let upstream = format!("origin/{}", branch);
+    call_on_path(
+        vec![
+            "git",
+            "merge",
+            "--allow-unrelated-histories",
+            "--strategy-option",
+            "theirs",
+            "--no-edit",
+            &upstream,
+        ],
+        dir,
+        transport,
+    )
+    .context("failed merging unrelated history in git")?;
+
+    Ok(())
+}
+
+/// Point the checked out branch to the fetched remote branch.
+pub fn reset_to_remote<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    branch: &str,
+) -> Result<()> {
+    let upstream = format!("origin/{}", branch);
+    call_on_path(vec!["git", "reset", "--hard", &upstream], dir, transport)
+        .context("failed resetting to remote branch in git")?;
+
+    Ok(())
 }
 
 /// Stage a specific file for committing.
-pub fn add_file<P: AsRef<Path>>(dir: &P, file: &str) -> Result<bool> {
-    call_on_path(vec!["git", "add", file], dir).context("failed adding file in git")
+pub fn add_file<P: AsRef<Path>>(dir: &P, transport: &TransportConfig, file: &str) -> Result<()> {
+    call_on_path(vec!["git", "add", file], dir, transport).context("failed adding file in git")?;
+
+    Ok(())
+}
+
+/// Whether the branch exists on the remote, fails when the remote can't be reached.
+pub fn remote_has_branch(transport: &TransportConfig, url: &str, branch: &str) -> Result<bool> {
+    // The remote isn't related to any directory
+    call_on_path_has_output(
+        vec!["git", "ls-remote", "--heads", url, branch],
+        &std::env::temp_dir(),
+        transport,
+    )
+    .context("failed listing remote branches in git")
+}
+
+/// Whether git knows about a file or any file in a directory.
+pub fn is_tracked<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+    file: &str,
+) -> Result<bool> {
+    call_on_path_has_output(vec!["git", "ls-files", "--", file], dir, transport)
 }
 
 /// Stage all files for committing.
-pub fn add_all_files<P: AsRef<Path>>(dir: &P) -> Result<bool> {
-    call_on_path(vec!["git", "add", "-A"], dir).context("failed adding all files in git")
+pub fn add_all_files<P: AsRef<Path>>(dir: &P, transport: &TransportConfig) -> Result<()> {
+    call_on_path(vec!["git", "add", "-A"], dir, transport)
+        .context("failed adding all files in git")?;
+
+    Ok(())
 }
 
 /// Do a git status to verify if there are local changes.
-pub fn has_changes<P: AsRef<Path>>(dir: &P) -> Result<bool> {
+pub fn has_changes<P: AsRef<Path>>(dir: &P, transport: &TransportConfig) -> Result<bool> {
     call_on_path_has_output(
         vec!["git", "ls-files", "--others", "--exclude-standard"],
         dir,
+        transport,
     )
     .context("failed checking if there are git changes")
 }
 
+/// Get the URL of the origin remote.
+pub fn remote_url<P: AsRef<Path>>(dir: &P, transport: &TransportConfig) -> Result<String> {
+    let output = call_on_path(vec!["git", "remote", "get-url", "origin"], dir, transport)
+        .context("failed getting remote URL in git")?;
+
+    Ok(str::from_utf8(&output.stdout)?.trim().to_string())
+}
+
+/// Get the name of the branch that's checked out.
+pub fn current_branch<P: AsRef<Path>>(dir: &P, transport: &TransportConfig) -> Result<String> {
+    let output = call_on_path(
+        vec!["git", "rev-parse", "--abbrev-ref", "HEAD"],
+        dir,
+        transport,
+    )
+    .context("failed getting current branch in git")?;
+
+    Ok(str::from_utf8(&output.stdout)?.trim().to_string())
+}
+
+/// Count the commits that aren't pushed and that aren't pulled yet, compared to the last fetch of
+/// the upstream branch.
+pub fn ahead_behind<P: AsRef<Path>>(
+    dir: &P,
+    transport: &TransportConfig,
+) -> Result<(usize, usize)> {
+    let output = call_on_path(
+        vec![
+            "git",
+            "rev-list",
+            "--left-right",
+            "--count",
+            "HEAD...@{upstream}",
+        ],
+        dir,
+        transport,
+    )
+    .context("failed comparing with upstream branch in git")?;
+
+    let counts = str::from_utf8(&output.stdout)?
+        .split_whitespace()
+        .map(|count| count.parse())
+        .collect::<Result<Vec<usize>, _>>()?;
+    match counts[..] {
+        [ahead, behind] => Ok((ahead, behind)),
+        _ => Err(anyhow!("Malformed git rev-list output")),
+    }
+}
+
 /// Initialize an empty repository
-pub fn init_repo<P: AsRef<Path>>(dir: &P) -> Result<bool> {
-    call_on_path(vec!["git", "init"], dir).context("Failed initializing the repository")
+pub fn init_repo<P: AsRef<Path>>(dir: &P, transport: &TransportConfig, branch: &str) -> Result<()> {
+    call_on_path(
+        vec!["git", "init", "--initial-branch", branch],
+        dir,
+        transport,
+    )
+    .context("Failed initializing the repository")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GitErrorKind, Signature};
+    use crate::config::TransportConfig;
+    use std::{fs, path::Path, process::Command};
+
+    /// Run git in the directory with a fixed identity.
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_build_command() {
+        let transport = TransportConfig {
+            git: Some("/opt/git/bin/git".to_string()),
+            config: vec!["http.proxy=http://proxy:8080".to_string()],
+            ..TransportConfig::default()
+        };
+        let command =
+            super::build_command(&["git", "fetch", "", "origin"], &".", &transport).unwrap();
+        assert_eq!(command.get_program(), "/opt/git/bin/git");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-c", "http.proxy=http://proxy:8080", "fetch", "origin"]
+        );
+
+        let command =
+            super::build_command(&["git", "status"], &".", &TransportConfig::default()).unwrap();
+        assert_eq!(command.get_program(), "git");
+    }
+
+    #[test]
+    fn test_file_history_first_parent() {
+        let dir = std::env::temp_dir().join(format!("emplace-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        git(&dir, &["init", "--quiet", "--initial-branch", "master"]);
+        fs::write(dir.join(".emplace"), "a\n").unwrap();
+        git(&dir, &["add", "."]);
+        git(&dir, &["commit", "--quiet", "-m", "first"]);
+
+        // A history that's merged in, like the one of another machine
+        git(&dir, &["checkout", "--quiet", "-b", "other"]);
+        fs::write(dir.join(".emplace"), "a\nb\n").unwrap();
+        git(&dir, &["commit", "--quiet", "-am", "other"]);
+        git(&dir, &["checkout", "--quiet", "master"]);
+        fs::write(dir.join("unrelated"), "").unwrap();
+        git(&dir, &["add", "."]);
+        git(&dir, &["commit", "--quiet", "-m", "unrelated"]);
+        git(
+            &dir,
+            &["merge", "--quiet", "--no-ff", "-m", "merge", "other"],
+        );
+
+        let subjects = super::file_history(&dir, &TransportConfig::default(), &[".emplace"])
+            .unwrap()
+            .into_iter()
+            .map(|commit| commit.subject)
+            .collect::<Vec<_>>();
+        assert_eq!(subjects, vec!["first", "merge"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_signed_by() {
+        let signature = |status, fingerprint: &str, key: &str| Signature {
+            commit: "0".repeat(40),
+            status,
+            fingerprint: fingerprint.to_string(),
+            key: key.to_string(),
+        };
+        let allowed = vec!["SHA256:abcdef".to_string(), "0123456789abcdef".to_string()];
+
+        assert!(signature('G', "SHA256:abcdef", "").is_signed_by(&allowed));
+        assert!(signature('U', "AAAA0123456789ABCDEF", "0123456789ABCDEF").is_signed_by(&allowed));
+        assert!(signature('G', "FFFF0123456789ABCDEF", "").is_signed_by(&allowed));
+        // Bad signatures and other keys aren't allowed
+        assert!(!signature('B', "SHA256:abcdef", "").is_signed_by(&allowed));
+        assert!(!signature('N', "", "").is_signed_by(&allowed));
+        assert!(!signature('G', "SHA256:other", "").is_signed_by(&allowed));
+        assert!(!signature('G', "SHA256:ABCDEF", "").is_signed_by(&allowed));
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            GitErrorKind::from_output("fatal: Authentication failed for 'https://example.com/'"),
+            GitErrorKind::Auth
+        );
+        assert_eq!(
+            GitErrorKind::from_output("git@example.com: Permission denied (publickey)."),
+            GitErrorKind::Auth
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "fatal: could not read Username for 'https://example.com': terminal prompts disabled"
+            ),
+            GitErrorKind::Auth
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "fatal: Unable to create '/mirrors/.git/index.lock': Permission denied"
+            ),
+            GitErrorKind::Permission
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "error: insufficient permission for adding an object to repository database .git/objects"
+            ),
+            GitErrorKind::Permission
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "fatal: unable to access 'https://example.com/': Could not resolve host: example.com"
+            ),
+            GitErrorKind::Network
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "fatal: couldn't find remote ref main\nfatal: the remote end hung up unexpectedly"
+            ),
+            GitErrorKind::MissingBranch
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                " ! [rejected]        master -> master (fetch first)\nerror: failed to push some refs"
+            ),
+            GitErrorKind::Conflict
+        );
+        assert_eq!(
+            GitErrorKind::from_output("CONFLICT (content): Merge conflict in .emplace"),
+            GitErrorKind::Conflict
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "fatal: '/tmp/mirors.git' does not appear to be a git repository\nfatal: Could not read from remote repository."
+            ),
+            GitErrorKind::BadRemote
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "ERROR: Repository not found.\nfatal: Could not read from remote repository."
+            ),
+            GitErrorKind::BadRemote
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "remote: Repository not found.\nfatal: repository 'https://github.com/user/mirors/' not found"
+            ),
+            GitErrorKind::BadRemote
+        );
+        assert_eq!(
+            GitErrorKind::from_output(
+                "ssh: connect to host example.com port 22: Connection refused\nfatal: Could not read from remote repository."
+            ),
+            GitErrorKind::Network
+        );
+        assert_eq!(
+            GitErrorKind::from_output("fatal: not a git repository"),
+            GitErrorKind::Other
+        );
+    }
 }
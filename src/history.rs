@@ -1,6 +1,7 @@
 use crate::{
     config::Config,
-    package::{Package, Packages},
+    declined::{self, Declined},
+    package::{Packages, ParseResult},
     repo::Repo,
 };
 use anyhow::Result;
@@ -13,6 +14,9 @@ use std::{
     path::Path,
 };
 
+/// Maximum amount of parse warnings printed for a history file.
+const MAX_PRINTED_WARNINGS: usize = 5;
+
 /// Capture a history file.
 pub fn history<P1, P2>(config_path: P1, path: P2, select_all: bool) -> Result<()>
 where
@@ -26,7 +30,38 @@ where
     let lines = reader.lines();
 
     // Extract the package information from the lines
-    let catches = filter_lines(lines);
+    let ParseResult {
+        packages: mut catches,
+        warnings,
+    } = filter_lines(lines);
+
+    // Summarize what might not be parsed correctly
+    let warnings = warnings
+        .into_iter()
+        .map(|warning| warning.to_string())
+        .unique()
+        .collect::<Vec<_>>();
+    if !warnings.is_empty() {
+        println!(
+            "{}",
+            format!(
+                "{} possible problems while parsing the history:",
+                warnings.len()
+            )
+            .yellow()
+        );
+        for warning in warnings.iter().take(MAX_PRINTED_WARNINGS) {
+            println!("- {}", warning);
+        }
+        if warnings.len() > MAX_PRINTED_WARNINGS {
+            println!("- and {} more", warnings.len() - MAX_PRINTED_WARNINGS);
+        }
+        println!(
+            "{}",
+            "Use `emplace explain <COMMAND>` to see how a command is parsed.".dimmed()
+        );
+    }
+
     if catches.is_empty() {
         return Ok(());
     };
@@ -34,10 +69,19 @@ where
     // Get the config
     let config = Config::from_path_or_new(&config_path)?;
 
+    // Don't ask about the packages that are never mirrored or have been declined before
+    let declined = Declined::load()?;
+    catches.retain(|package| !config.ignore.matches(package) && !declined.contains(package));
+
     // Get the repository from the config
+    let accept = config.accept.clone();
     let repo = Repo::new(config, true)?;
+    if repo.is_read_only() {
+        println!("The storage is read-only, packages can't be mirrored.");
+
+        return Ok(());
+    }
 
-    let mut catches = Packages::from(catches);
     catches.filter_saved_packages(&repo.read()?);
     catches.dedup();
 
@@ -52,9 +96,12 @@ where
     } else {
         // Prompt the user for the packages to sync
         let colored_selection: Vec<String> = catches.iter().map(|x| x.color_full_name()).collect();
+        // The packages that are mirrored without asking when caught are selected already
+        let accepted: Vec<bool> = catches.iter().map(|x| accept.matches(x)).collect();
 
         MultiSelect::new()
             .items(&colored_selection)
+            .defaults(&accepted)
             .with_prompt("Select packages to sync")
             .interact()?
     };
@@ -64,12 +111,14 @@ where
         .iter()
         .for_each(|x| checked.push(catches[*x].clone()));
 
+    let unchecked = catches.difference(&Packages::from(checked.clone()));
+
     let len = checked.len();
     if len == 0 {
         // Nothing found after filtering
         println!("Nothing is checked");
 
-        return Ok(());
+        return decline_unchecked(&unchecked);
     }
 
     // Print the info
@@ -90,32 +139,115 @@ where
     repo.mirror(Packages::from(checked))
         .expect("Could not mirror commands");
 
+    decline_unchecked(&unchecked)
+}
+
+/// Ask whether the packages that aren't selected should be asked about again.
+fn decline_unchecked(unchecked: &Packages) -> Result<()> {
+    if unchecked.is_empty() {
+        return Ok(());
+    }
+
+    let decline = dialoguer::Confirm::new()
+        .with_prompt(format!(
+            "Don't ask about the {} unselected packages again?",
+            unchecked.len()
+        ))
+        .default(false)
+        .interact()?;
+    if decline {
+        declined::decline(unchecked)?;
+    }
+
     Ok(())
 }
 
-/// Extract the packages from the history lines.
-pub fn filter_lines<R>(lines: Lines<R>) -> Vec<Package>
+/// Extract the packages from the history lines with the warnings that occurred while parsing them.
+pub fn filter_lines<R>(lines: Lines<R>) -> ParseResult
 where
     R: BufRead,
 {
     // Filter actual lines
-    lines
-        .map_while(Result::ok)
-        .map(|x| x.split_whitespace().join(" "))
+    history_entries(lines)
+        .into_iter()
+        // Skip the commands that are known to have failed
+        .filter(|(_, status)| status.is_none_or(|status| status == 0))
+        .map(|(command, _)| command.split_whitespace().join(" "))
         // Remove duplicates
         .sorted()
         .dedup()
-        // Get an iterator over all the matches in the line
-        .map(|x| Packages::from_line(&x))
-        // Make it a big list
-        .flat_map(|packages| packages.into_iter())
-        .collect()
+        // Parse all the lines into one big result
+        .fold(ParseResult::default(), |mut result, line| {
+            result.append(&mut Packages::parse_line(&line));
+
+            result
+        })
+}
+
+/// Split the history lines into commands with their exit status if the history format records it.
+fn history_entries<R>(lines: Lines<R>) -> Vec<(String, Option<i32>)>
+where
+    R: BufRead,
+{
+    let mut entries: Vec<(String, Option<i32>)> = vec![];
+
+    for line in lines.map_while(Result::ok) {
+        // Fish style history stores the exit status as a field below the command
+        if let Some(status) = line
+            .trim_start()
+            .strip_prefix("exit:")
+            .and_then(|status| status.trim().parse().ok())
+        {
+            if let Some((_, last_status)) = entries
+                .iter_mut()
+                .rev()
+                .find(|(command, _)| command.starts_with("- cmd:"))
+            {
+                *last_status = Some(status);
+            }
+
+            continue;
+        }
+
+        // Atuin exported with `--format "{exit}\t{command}"` prefixes the command with the status
+        if let Some((status, command)) = line
+            .split_once('\t')
+            .and_then(|(status, command)| Some((status.trim().parse().ok()?, command)))
+        {
+            entries.push((command.to_string(), Some(status)));
+
+            continue;
+        }
+
+        entries.push((line, None));
+    }
+
+    entries
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::{collection::vec, prelude::*};
     use std::io::{BufRead, Cursor};
 
+    proptest! {
+        #[test]
+        fn prop_filter_lines(lines in vec("\\PC*", 0..8)) {
+            let history = lines.join("\n");
+
+            for package in super::filter_lines(Cursor::new(&history).lines()) {
+                prop_assert!(history.contains(package.name()));
+            }
+        }
+
+        #[test]
+        fn prop_failed_commands(status in 1..256, command in "sudo apt install [a-z]{1,8}") {
+            let history = format!("{}\t{}\n", status, command);
+
+            prop_assert!(super::filter_lines(Cursor::new(&history).lines()).packages.is_empty());
+        }
+    }
+
     #[test]
     fn test_bash_history() {
         let bash_history = Cursor::new(indoc::indoc! {"
@@ -125,7 +257,7 @@ mod tests {
             fg
         "});
 
-        let packages = super::filter_lines(bash_history.lines());
+        let packages = super::filter_lines(bash_history.lines()).packages;
         assert_eq!(packages.len(), 1);
     }
 
@@ -140,7 +272,7 @@ mod tests {
               when: 1575643236
         "#});
 
-        let packages = super::filter_lines(fish_history.lines());
+        let packages = super::filter_lines(fish_history.lines()).packages;
         assert_eq!(packages.len(), 2);
     }
 
@@ -156,7 +288,54 @@ mod tests {
             : 1610989600:0;nvim .zsh_history
         "#});
 
-        let packages = super::filter_lines(zsh_history.lines());
+        let packages = super::filter_lines(zsh_history.lines()).packages;
+        assert_eq!(packages.len(), 2);
+    }
+
+    #[test]
+    fn test_fish_history_exit_status() {
+        let fish_history = Cursor::new(indoc::indoc! {r#"
+            - cmd: sudo apt install fzf
+              when: 1575643236
+              exit: 0
+            - cmd: sudo apt install nonexistent
+              when: 1575643236
+              exit: 100
+            - cmd: sudo apt install meld
+              when: 1575643236
+        "#});
+
+        let packages = super::filter_lines(fish_history.lines()).packages;
+        assert_eq!(packages.len(), 2);
+        assert!(packages
+            .iter()
+            .all(|package| package.name() != "nonexistent"));
+    }
+
+    #[test]
+    fn test_atuin_history() {
+        let atuin_history = Cursor::new(
+            "0\tsudo apt install fzf\n100\tsudo apt install nonexistent\n0\tcargo install test\n",
+        );
+
+        let packages = super::filter_lines(atuin_history.lines()).packages;
         assert_eq!(packages.len(), 2);
+        assert!(packages
+            .iter()
+            .all(|package| package.name() != "nonexistent"));
+    }
+
+    #[test]
+    fn test_history_warnings() {
+        let bash_history = Cursor::new(indoc::indoc! {"
+            sudo apt install --target-release bookworm-backports test
+            sudo apt install test
+        "});
+
+        let result = super::filter_lines(bash_history.lines());
+        assert!(result
+            .warnings
+            .iter()
+            .any(|warning| warning.to_string().contains("`--target-release`")));
     }
 }
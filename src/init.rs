@@ -1,8 +1,8 @@
-use crate::public_clap_app;
+use crate::{alias::Aliases, public_clap_app};
 use anyhow::{Context, Result};
 use camino::Utf8Path;
 use clap_complete::shells::{Bash, Fish, Zsh};
-use std::{env, io};
+use std::{env, fs, io};
 
 pub fn init_main(config_path: &Utf8Path, shell_name: &str) -> Result<()> {
     let exe_path = env::current_exe()
@@ -11,6 +11,14 @@ pub fn init_main(config_path: &Utf8Path, shell_name: &str) -> Result<()> {
         .into_string()
         .expect("Could not convert path to string");
 
+    // Create the directory where the shell hooks can dump the aliases
+    let aliases_dir = Aliases::directory();
+    if aliases_dir.is_file() {
+        // Older versions dumped the aliases of all shells into a single file
+        fs::remove_file(&aliases_dir).context("removing old aliases file")?;
+    }
+    fs::create_dir_all(&aliases_dir).context("creating directory for aliases files")?;
+
     let setup_script = match shell_name {
         "bash" => BASH_INIT,
         "zsh" => ZSH_INIT,
@@ -33,6 +41,8 @@ pub fn init_main(config_path: &Utf8Path, shell_name: &str) -> Result<()> {
                     .unwrap_or_else(|_| config_path.to_path_buf())
                     .as_str()
             )
+            // Replace aliases directory
+            .replace("## EMPLACE_ALIASES_DIR ##", aliases_dir.as_str())
     );
 
     // Print the completions
@@ -52,16 +62,29 @@ pub fn init_main(config_path: &Utf8Path, shell_name: &str) -> Result<()> {
 
 const BASH_INIT: &str = r###"
 emplace_postexec_invoke_exec () {
-    # quit when the previous command failed
-    [ -z "$?" ] && exit $?
+    # store the exit status of the previous command before it gets overwritten
+    local exit_status=$?
 
     local hist=`history 1`
 
     local this_command=`HISTTIMEFORMAT= echo $hist | sed -e "s/^[ ]*[0-9]*[ ]*//"`;
-    ## EMPLACE ## catch "$this_command"
+
+    # dump the aliases of this shell so emplace can expand them, only when they changed or when
+    # a file of an earlier shell with the same PID is left behind
+    local aliases_file="## EMPLACE_ALIASES_DIR ##/$$"
+    local aliases=`alias`
+    if [ -z "${emplace_aliases+set}" ] || [ "$aliases" != "$emplace_aliases" ] || [ ! -e "$aliases_file" ]; then
+        emplace_aliases="$aliases"
+        echo "$aliases" > "$aliases_file"
+    fi
+
+    ## EMPLACE ## catch --status "$exit_status" --aliases "$aliases_file" "$this_command"
 }
 PROMPT_COMMAND="emplace_postexec_invoke_exec;$PROMPT_COMMAND"
 
+# Remove the aliases of this shell when it exits
+trap 'rm -f "## EMPLACE_ALIASES_DIR ##/$$"' EXIT
+
 export EMPLACE_CONFIG="## EMPLACE_CONFIG_PATH ##"
 
 # Add an empty line so new shells won't display the last installable command
@@ -69,39 +92,58 @@ echo "# emplace" >> $HISTFILE
 "###;
 
 const ZSH_INIT: &str = r###"
+emplace_preexec() {
+    # the full command that will be executed, with the aliases expanded
+    emplace_this_command="$3"
+}
+
 emplace_precmd() {
-    # quit when the previous command failed
-    [ -z "$?" ] && exit
+    # store the exit status of the previous command before it gets overwritten
+    local exit_status=$?
 
-    local hist=`history -1`
+    # quit when no command has been executed
+    [ -z "$emplace_this_command" ] && return
 
-    local this_command=`HISTTIMEFORMAT= echo $hist | sed -e "s/^[ ]*[0-9]*[ ]*//"`;
-    ## EMPLACE ## catch "$this_command"
+    local this_command="$emplace_this_command"
+    unset emplace_this_command
+
+    ## EMPLACE ## catch --status "$exit_status" "$this_command"
 }
 # Don't hook them double in nested shells
+if [[ ${preexec_functions[(ie)emplace_preexec]} -gt ${#preexec_functions} ]]; then
+    preexec_functions+=(emplace_preexec)
+fi
 if [[ ${precmd_functions[(ie)emplace_precmd]} -gt ${#precmd_functions} ]]; then
     precmd_functions+=(emplace_precmd)
 fi
 
 export EMPLACE_CONFIG="## EMPLACE_CONFIG_PATH ##"
-
-# Add an empty line so new shells won't display the last installable command
-echo "# emplace" >> $HISTFILE
 "###;
 
 const FISH_INIT: &str = r###"
 function emplace_postcmd --on-event fish_postexec
-    # quit when the previous command failed
-    if test $status -gt 0
-        return
+    # store the exit status of the previous command before it gets overwritten
+    set -l exit_status $status
+
+    # dump the aliases of this shell so emplace can expand them, only when they changed or when
+    # a file of an earlier shell with the same PID is left behind
+    set -l aliases_file "## EMPLACE_ALIASES_DIR ##/$fish_pid"
+    set -l aliases (alias | string collect)
+    if not set -q emplace_aliases; or test "$aliases" != "$emplace_aliases"; or not test -e "$aliases_file"
+        set -g emplace_aliases $aliases
+        printf '%s\n' "$aliases" > "$aliases_file"
     end
 
-    ## EMPLACE ## catch "$argv"
+    ## EMPLACE ## catch --status $exit_status --aliases "$aliases_file" "$argv"
+end
+
+function emplace_exit --on-event fish_exit
+    rm -f "## EMPLACE_ALIASES_DIR ##/$fish_pid"
 end
 
 set -x EMPLACE_CONFIG "## EMPLACE_CONFIG_PATH ##"
 "###;
 
 const NU_INIT: &str = r###"
-EMPLACE_CONFIG="## EMPLACE_CONFIG_PATH ##" ## EMPLACE ## catch $(history | last); echo >
+EMPLACE_CONFIG="## EMPLACE_CONFIG_PATH ##" ## EMPLACE ## catch --status $env.LAST_EXIT_CODE $(history | last); echo >
 "###;
@@ -2,7 +2,10 @@ use crate::{config::Config, package::Package, repo::Repo};
 use anyhow::{anyhow, Context, Result};
 use dialoguer::MultiSelect;
 use log::{debug, error};
-use std::{path::Path, process::Command};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 pub fn install<P>(config_path: P, install_all: bool) -> Result<()>
 where
@@ -64,9 +67,16 @@ where
             let install_command = package.install_command();
             debug!("Installing: {}.", install_command);
 
-            match call(install_command.split_ascii_whitespace().collect()) {
+            // Package files are stored relative to the repository
+            let dir = if package.is_artifact() {
+                repo.path()
+            } else {
+                PathBuf::from(".")
+            };
+
+            match call(&package.install_args(), &dir) {
                 Ok(_) => println!("{} installed successfully.", package.color_full_name()),
-                Err(err) => error!("\"{}\": {:?}", package.install_command(), err),
+                Err(err) => error!("\"{}\": {:?}", install_command, err),
             };
         }
     }
@@ -74,16 +84,14 @@ where
     Ok(())
 }
 
-fn call(command: Vec<&str>) -> Result<()> {
-    let mut iter = command.iter();
-    let cmd_name = iter.next().unwrap();
+fn call(command: &[String], dir: &Path) -> Result<()> {
+    let (cmd_name, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("install command is empty"))?;
 
     let mut cmd = Command::new(cmd_name);
-    for arg in iter {
-        if !arg.is_empty() {
-            cmd.arg(arg);
-        }
-    }
+    cmd.current_dir(dir);
+    cmd.args(args);
 
     let result = cmd.output()?;
     // Return stderr when the command failed
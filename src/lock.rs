@@ -0,0 +1,105 @@
+use anyhow::{bail, Context, Result};
+use fs2::FileExt;
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait before trying to take a lock again.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for another process that's holding a lock.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An exclusive advisory lock on a file, released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Wait until the lock on the file can be taken, giving up after the timeout.
+    pub fn acquire_timeout<P: AsRef<Path>>(path: P, timeout: Duration) -> Result<Self> {
+        let file = Self::open(&path)?;
+
+        let start = Instant::now();
+        let mut waiting = false;
+        while file.try_lock_exclusive().is_err() {
+            if start.elapsed() >= timeout {
+                bail!(
+                    "another emplace process has been holding the lock \"{}\" for more than {} seconds, try again after it's finished",
+                    path.as_ref().display(),
+                    timeout.as_secs()
+                );
+            }
+
+            if !waiting {
+                println!("Waiting for another emplace process to finish..");
+                waiting = true;
+            }
+
+            thread::sleep(RETRY_INTERVAL);
+        }
+
+        Ok(Self { file })
+    }
+
+    /// Get the path of the lock file for a directory that's shared between processes.
+    pub fn path_for<P: AsRef<Path>>(dir: P) -> PathBuf {
+        dirs::cache_dir()
+            .expect("Could not find cache dir")
+            .join("emplace")
+            .join("locks")
+            .join(format!("{}.lock", path_name(dir)))
+    }
+
+    /// Open or create the lock file.
+    fn open<P: AsRef<Path>>(path: P) -> Result<File> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir).context("creating directory for lock file")?;
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .context("opening lock file")
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // The lock is also released when the file is closed, so failing here isn't a problem
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Use the full path as a file name, so files for different directories don't collide.
+pub fn path_name<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref()
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileLock;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timeout() {
+        let path = std::env::temp_dir().join(format!("emplace-lock-{}.lock", std::process::id()));
+
+        let lock = FileLock::acquire_timeout(&path, Duration::from_millis(200)).unwrap();
+        // The same file can't be locked twice, even from the same process
+        assert!(FileLock::acquire_timeout(&path, Duration::from_millis(200)).is_err());
+
+        drop(lock);
+        assert!(FileLock::acquire_timeout(&path, Duration::from_millis(200)).is_ok());
+    }
+}
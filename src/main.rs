@@ -1,17 +1,29 @@
+mod alias;
 mod catch;
 mod clean;
 mod config;
+mod configure;
+mod declined;
+mod doctor;
+mod explain;
+mod filter;
 mod git;
 mod history;
 mod init;
 mod install;
+mod lock;
 mod migrate;
 mod package;
 mod package_manager;
 mod package_manager_impl;
+mod queue;
 mod repo;
+mod storage;
+mod sync;
+mod timeline;
+mod undo;
 
-use crate::config::Config;
+use crate::{config::Config, doctor::DoctorCollector};
 use anyhow::{anyhow, Context, Result};
 use bugreport::{
     bugreport,
@@ -22,7 +34,6 @@ use camino::Utf8PathBuf;
 use clap::{Arg, ArgAction, ColorChoice, Command};
 use log::error;
 use simplelog::{ColorChoice as LogColorChoice, LevelFilter, TermLogger, TerminalMode};
-use std::path::PathBuf;
 
 fn public_clap_app() -> Command {
     clap::command!()
@@ -84,6 +95,23 @@ fn safe_main() -> Result<()> {
 						.value_name("LINE")
 						.help("The command as entired in the terminal")
 						.required(true)
+                        .action(ArgAction::Set)
+				)
+				.arg(
+					Arg::new("status")
+						.long("status")
+						.value_name("CODE")
+						.help("The exit status of the command, failed commands are ignored")
+						.allow_negative_numbers(true)
+						.value_parser(clap::value_parser!(i32))
+                        .action(ArgAction::Set)
+				)
+				.arg(
+					Arg::new("aliases")
+						.long("aliases")
+						.value_name("PATH")
+						.help("File containing the output of the shell `alias` builtin, used to expand the line")
+						.value_parser(clap::value_parser!(Utf8PathBuf))
                         .action(ArgAction::Set)
 				),
 		)
@@ -93,7 +121,7 @@ fn safe_main() -> Result<()> {
 				.arg(
 					Arg::new("history_file")
                         .value_name("PATH")
-						.help("Path to shell history file")
+						.help("Path to shell history file\nFailed commands are skipped when the history records exit codes, for example fish or `atuin history list --format \"{exit}\\t{command}\"`")
 						.required(true)
 						.value_parser(clap::value_parser!(Utf8PathBuf))
                         .action(ArgAction::Set)
@@ -106,6 +134,66 @@ fn safe_main() -> Result<()> {
                         .action(ArgAction::SetTrue)
                 ),
 		)
+        .subcommand(
+            Command::new("log")
+            .about("Show when packages are added and removed, and from which machine")
+            .arg(
+                Arg::new("max-count")
+                .short('n')
+                .long("max-count")
+                .value_name("NUMBER")
+                .help("Only show the most recent changes")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            ),
+        )
+        .subcommand(
+            Command::new("undo")
+            .about("Revert the packages added or removed by the last mirror or clean")
+            .arg(
+                Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Don't ask for confirmation")
+                .action(ArgAction::SetTrue)
+            ),
+        )
+        .subcommand(
+            Command::new("sync")
+            .about("Share the mirrored packages that couldn't be shared without a connection")
+            .arg(
+                Arg::new("rebase")
+                .long("rebase")
+                .help("Rebase the local commits on top of the remote instead of merging")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("background")
+                .long("background")
+                .help("Store failures so they are reported at the next prompt")
+                .hide(true)
+                .action(ArgAction::SetTrue)
+            ),
+        )
+        .subcommand(
+            Command::new("explain")
+            .about("Show how a command is parsed, to find out why something is or isn't caught")
+            .arg(
+                Arg::new("line")
+                .value_name("LINE")
+                .help("The command as entered in the terminal")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("aliases")
+                .long("aliases")
+                .value_name("PATH")
+                .help("File containing the output of the shell `alias` builtin, used to expand the line")
+                .value_parser(clap::value_parser!(Utf8PathBuf))
+                .action(ArgAction::Set)
+            ),
+        )
         .subcommand(
             Command::new("config")
             .about("Provides options for managing configuration")
@@ -122,8 +210,76 @@ fn safe_main() -> Result<()> {
                 .long("path")
                 .help("Print out path to config")
                 .action(ArgAction::SetTrue)
+            )
+            .subcommand(
+                Command::new("get")
+                .about("Print the value of a setting, like `repo.url`")
+                .arg(
+                    Arg::new("key")
+                    .help("The setting, with the sections separated by dots")
+                    .required(true)
+                    .action(ArgAction::Set)
+                ),
+            )
+            .subcommand(
+                Command::new("set")
+                .about("Change the value of a setting, like `repo.branch main`")
+                .arg(
+                    Arg::new("key")
+                    .help("The setting, with the sections separated by dots")
+                    .required(true)
+                    .action(ArgAction::Set)
+                )
+                .arg(
+                    Arg::new("value")
+                    .help("The new value, strings don't need to be quoted")
+                    .required(true)
+                    .action(ArgAction::Set)
+                ),
+            )
+            .subcommand(
+                Command::new("edit")
+                .about("Open the config in your editor and check it before saving")
+            )
+            .subcommand(
+                Command::new("validate")
+                .about("Check the config for mistakes and whether the remote can be reached")
+            )
+            .subcommand(
+                Command::new("set-remote")
+                .about("Synchronize the mirrors with a remote repository, also when they were only kept on this machine")
+                .arg(
+                    Arg::new("url")
+                    .help("The URL of the git repository")
+                    .required(true)
+                    .action(ArgAction::Set)
+                ),
+            ),
+        )
+        .subcommand(
+            Command::new("declined")
+            .about("Manage the packages you chose not to mirror on this machine")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("list")
+                .about("Print the packages that aren't asked about anymore")
+            )
+            .subcommand(
+                Command::new("forget")
+                .about("Ask about declined packages again, select them when no names are given")
+                .arg(
+                    Arg::new("names")
+                    .value_name("NAME")
+                    .help("Names of the packages to forget")
+                    .num_args(0..)
+                    .action(ArgAction::Append)
+                ),
             ),
         )
+        .subcommand(
+            Command::new("doctor")
+            .about("Check whether everything is set up correctly and how to fix it")
+        )
         .subcommand(
             Command::new("bugreport")
             .about("Collect and print information that can be send along with a bug report")
@@ -143,8 +299,10 @@ fn safe_main() -> Result<()> {
         }
         Some(("catch", sub_m)) => {
             let line: &String = sub_m.get_one("line").context("line is missing")?;
+            let status: Option<i32> = sub_m.get_one("status").copied();
+            let aliases_path: Option<&Utf8PathBuf> = sub_m.get_one("aliases");
 
-            catch::catch(config_path, line).context("catching a command")
+            catch::catch(config_path, line, status, aliases_path).context("catching a command")
         }
         Some(("install", sub_m)) => {
             install::install(config_path, sub_m.get_flag("yes")).context("installing packages")
@@ -158,21 +316,73 @@ fn safe_main() -> Result<()> {
             history::history(config_path, hist_path, sub_m.get_flag("yes"))
                 .context("capturing history")
         }
+        Some(("log", sub_m)) => timeline::log(config_path, sub_m.get_one("max-count").copied())
+            .context("showing mirror history"),
+        Some(("undo", sub_m)) => {
+            undo::undo(config_path, sub_m.get_flag("yes")).context("undoing last change")
+        }
+        Some(("sync", sub_m)) => sync::sync(
+            config_path,
+            sub_m.get_flag("rebase"),
+            sub_m.get_flag("background"),
+        )
+        .context("synchronizing"),
+        Some(("explain", sub_m)) => {
+            let line: &String = sub_m.get_one("line").context("line is missing")?;
+            let aliases_path: Option<&Utf8PathBuf> = sub_m.get_one("aliases");
+
+            explain::explain(line, aliases_path).context("explaining a command")
+        }
         // Config subcommand, if path is present and new is not
         // it will just print the default path for the config file,
         // otherwise it will create a new config and ask what to do about the repository
-        Some(("config", subm)) => {
-            if subm.get_flag("path") && !subm.get_flag("new") {
+        Some(("config", subm)) => match subm.subcommand() {
+            Some(("get", subm)) => {
+                let key = subm.get_one::<String>("key").expect("key is required");
+
+                configure::get(config_path, key).context("getting config value")
+            }
+            Some(("set", subm)) => {
+                let key = subm.get_one::<String>("key").expect("key is required");
+                let value = subm.get_one::<String>("value").expect("value is required");
+
+                configure::set(config_path, key, value).context("setting config value")
+            }
+            Some(("edit", _)) => configure::edit(config_path).context("editing config"),
+            Some(("validate", _)) => configure::validate(config_path).context("validating config"),
+            Some(("set-remote", subm)) => {
+                let url = subm.get_one::<String>("url").expect("URL is required");
+
+                let mut config = Config::from_path_or_new(&config_path)?;
+                config
+                    .set_remote(&config_path, url)
+                    .context("setting remote")
+            }
+            _ if subm.get_flag("path") && !subm.get_flag("new") => {
                 println!("Your config path is {config_path}");
 
                 Ok(())
-            } else {
-                let mut config = config::Config::new(config_path)?;
-                config.clone_repo_ask()?;
+            }
+            _ => {
+                let mut config = config::Config::new(&config_path)?;
+                config.clone_repo_ask(&config_path)?;
 
                 Ok(())
             }
-        }
+        },
+        Some(("declined", subm)) => match subm.subcommand() {
+            Some(("forget", subm)) => {
+                let names = subm
+                    .get_many::<String>("names")
+                    .unwrap_or_default()
+                    .map(|name| name.as_str())
+                    .collect::<Vec<_>>();
+
+                declined::forget(&names).context("forgetting declined packages")
+            }
+            _ => declined::list().context("listing declined packages"),
+        },
+        Some(("doctor", _)) => doctor::doctor(config_path).context("checking health"),
         // Print information that can be used in bug report tickets
         Some(("bugreport", _)) => {
             bugreport!()
@@ -180,6 +390,7 @@ fn safe_main() -> Result<()> {
                 .info(EnvironmentVariables::list(&["SHELL", "EMPLACE_CONFIG"]))
                 .info(CommandOutput::new("Git version", "git", &["--version"]))
                 .info(CompileTimeInformation::default())
+                .info(DoctorCollector::new(&config_path))
                 .print::<Markdown>();
 
             Ok(())
@@ -1,15 +1,20 @@
-use crate::package_manager::{PackageManager, PackageManagerTrait};
-use anyhow::Result;
+use crate::package_manager::{ArgumentKind, PackageManager, PackageManagerTrait, ParseWarning};
+use anyhow::{anyhow, Context, Result};
 use colored::Colorize;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
+    fs,
     iter::{self, IntoIterator},
-    ops::Deref,
+    ops::{Deref, Not},
+    path::PathBuf,
     string::String,
 };
 
+/// Directory in the repository where package files copied from a machine are stored.
+pub const ARTIFACTS_DIR: &str = "artifacts";
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Package {
     /// The package manager this package belongs to.
@@ -19,6 +24,9 @@ pub struct Package {
     /// A list of command line flags this package should be installed with.
     #[serde(default)]
     flags: Vec<String>,
+    /// Whether the name is the path of a package file copied into the repository.
+    #[serde(default, skip_serializing_if = "Not::not")]
+    artifact: bool,
 }
 
 impl Package {
@@ -28,37 +36,102 @@ impl Package {
             source,
             name,
             flags,
+            artifact: false,
         }
     }
 
+    /// Instantiate a package file that lives in the repository, the path is relative to it.
+    pub fn artifact(source: PackageManager, path: String, flags: Vec<String>) -> Self {
+        Self {
+            source,
+            name: path,
+            flags,
+            artifact: true,
+        }
+    }
+
+    /// The package manager this package belongs to.
+    pub fn source(&self) -> PackageManager {
+        self.source
+    }
+
     /// Only the package name.
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// The name that's used to check whether the package is installed.
+    ///
+    /// For package files this strips the directory, extension and version from the file name.
+    pub fn installed_name(&self) -> &str {
+        if !self.artifact {
+            return &self.name;
+        }
+
+        let file_name = self.name.rsplit(['/', '\\']).next().unwrap_or(&self.name);
+
+        // Debian uses `name_version_arch.deb`, most others `name-version-release.ext`
+        let end = file_name
+            .match_indices('-')
+            .map(|(index, _)| index)
+            .find(|index| file_name[index + 1..].starts_with(|c: char| c.is_ascii_digit()))
+            .into_iter()
+            .chain(file_name.find('_'))
+            .min()
+            .or_else(|| file_name.find('.'))
+            .unwrap_or(file_name.len());
+
+        &file_name[..end]
+    }
+
     /// The name including the flags.
     pub fn full_command(&self) -> String {
         self.flags.iter().chain(iter::once(&self.name)).join(" ")
     }
 
-    /// The full command needed to install this package.
-    #[cfg(not(target_os = "windows"))]
+    /// The full command needed to install this package, only for showing it.
     pub fn install_command(&self) -> String {
+        self.install_args().join(" ")
+    }
+
+    /// The program and arguments needed to install this package, names and paths with spaces
+    /// are kept as a single argument.
+    ///
+    /// Package files must be installed from the repository directory.
+    pub fn install_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        #[cfg(not(target_os = "windows"))]
         if self.source.needs_root() {
-            format!(
-                "sudo {} {}",
-                self.source.install_command(),
-                self.full_command()
-            )
-        } else {
-            format!("{} {}", self.source.install_command(), self.full_command())
+            args.push("sudo".to_string());
         }
+
+        let (install_command, name) = match self.source.install_local_command() {
+            // Prefix it so package managers know it's a file
+            Some(install_local_command) if self.artifact => {
+                (install_local_command, format!("./{}", self.name))
+            }
+            _ => (self.source.install_command(), self.name.clone()),
+        };
+        args.extend(
+            install_command
+                .split_whitespace()
+                .map(|arg| arg.to_string()),
+        );
+        // Flags with a value are stored together
+        args.extend(
+            self.flags
+                .iter()
+                .flat_map(|flag| flag.split_whitespace())
+                .map(|arg| arg.to_string()),
+        );
+        args.push(name);
+
+        args
     }
 
-    /// The full command needed to install this package.
-    #[cfg(target_os = "windows")]
-    pub fn install_command(&self) -> String {
-        format!("{} {}", self.source.install_command(), self.full_command())
+    /// Whether the name is the path of a package file copied into the repository.
+    pub fn is_artifact(&self) -> bool {
+        self.artifact
     }
 
     /// The full name in fancy colors.
@@ -98,6 +171,112 @@ impl Package {
     }
 }
 
+/// A file on this machine passed to a package manager, which can't be mirrored as is.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LocalFile {
+    /// The package manager the file is passed to.
+    source: PackageManager,
+    /// Whether it's a package file or a file listing packages.
+    kind: ArgumentKind,
+    /// The path as it's written in the command.
+    path: String,
+    /// A list of command line flags the file is installed with.
+    flags: Vec<String>,
+}
+
+impl LocalFile {
+    /// Instantiate.
+    pub fn new(
+        source: PackageManager,
+        kind: ArgumentKind,
+        path: String,
+        flags: Vec<String>,
+    ) -> Self {
+        Self {
+            source,
+            kind,
+            path,
+            flags,
+        }
+    }
+
+    /// Parse a line into a list of local files.
+    pub fn from_line(line: &str) -> Vec<Self> {
+        split_line(line)
+            .flat_map(|(line, package_manager)| package_manager.catch_local_files(line))
+            .collect()
+    }
+
+    /// Whether it's a package file or a file listing packages.
+    pub fn kind(&self) -> ArgumentKind {
+        self.kind
+    }
+
+    /// The path in fancy colors.
+    pub fn color_full_name(&self) -> String {
+        format!(
+            "{} ({})",
+            self.path.yellow(),
+            self.source.full_name().green()
+        )
+    }
+
+    /// The path resolved relative to the current directory.
+    pub fn resolved_path(&self) -> PathBuf {
+        match self.path.strip_prefix("~/").zip(dirs::home_dir()) {
+            Some((path, home_dir)) => home_dir.join(path),
+            None => PathBuf::from(&self.path),
+        }
+    }
+
+    /// Whether the file can be copied into the repository and installed from there.
+    pub fn can_be_copied(&self) -> bool {
+        self.kind == ArgumentKind::LocalPath
+            && self.source.install_local_command().is_some()
+            && self.resolved_path().is_file()
+    }
+
+    /// The package pointing to the copy of the file in the repository.
+    pub fn artifact_package(&self) -> Result<Package> {
+        let file_name = self
+            .resolved_path()
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map(|file_name| file_name.to_string())
+            .ok_or_else(|| anyhow!("\"{}\" is not a valid file name", self.path))?;
+
+        Ok(Package::artifact(
+            self.source,
+            format!("{}/{}", ARTIFACTS_DIR, file_name),
+            self.flags.clone(),
+        ))
+    }
+
+    /// Read the packages listed in a requirements file.
+    pub fn requirements(&self) -> Result<Packages> {
+        let contents = fs::read_to_string(self.resolved_path())
+            .with_context(|| format!("reading requirements file \"{}\"", self.path))?;
+
+        Ok(Packages(
+            contents
+                .lines()
+                // Remove the comments
+                .map(|line| line.split(" #").next().unwrap_or_default().trim())
+                // Skip empty lines, comments and options like `-r other.txt`
+                .filter(|line| line.starts_with(|c: char| c.is_alphanumeric()))
+                // Packages installed from version control or a URL can't be installed by name
+                .filter(|line| !line.contains("://"))
+                // Only keep the name without version specifiers, extras or markers
+                .filter_map(|line| {
+                    line.split(['=', '<', '>', '!', '~', '[', ';', '@', ' '])
+                        .next()
+                })
+                .map(|name| Package::new(self.source, name.to_string(), self.flags.clone()))
+                .collect(),
+        ))
+    }
+}
+
 impl Ord for Package {
     fn cmp(&self, other: &Self) -> Ordering {
         self.full_command().cmp(&other.full_command())
@@ -110,7 +289,7 @@ impl PartialOrd for Package {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Packages(Vec<Package>);
 
 impl Packages {
@@ -119,22 +298,31 @@ impl Packages {
         Self(vec![])
     }
 
-    /// Parse a line into a list of packages.
-    pub fn from_line(line: &str) -> Self {
-        // First we split the line into separating characters
-        let lines = line
-            .split(|c| c == ';' || c == '|' || c == '&' || c == '\r' || c == '\n')
-            // Then try to find the proper package manager for each line, this also filters out
-            // lines that are not related to the package manager
-            .flat_map(|line| {
-                // Attempt to find a matching package managers with the line
-                PackageManager::from_line_iter(line).map(move |manager| (line, manager))
-            })
-            // Parse the packages in the line with the package manager supplied
-            .flat_map(|(line, package_manager)| package_manager.catch(line))
-            .collect();
+    /// Parse a line into a list of packages with the warnings that occurred while parsing it.
+    pub fn parse_line(line: &str) -> ParseResult {
+        let mut result = ParseResult::default();
+
+        for command in split_commands(line) {
+            let mut managers = vec![];
+            for package_manager in PackageManager::from_line_iter(command) {
+                // Parse the packages in the command with the package manager supplied
+                let mut parsed = package_manager.catch(command);
+                if !parsed.packages.is_empty() {
+                    managers.push(package_manager);
+                }
+                result.append(&mut parsed);
+            }
+
+            // It's not clear which package manager is meant
+            if managers.len() > 1 {
+                result.warnings.push(ParseWarning::AmbiguousManagers {
+                    command: command.trim().to_string(),
+                    managers,
+                });
+            }
+        }
 
-        Self(lines)
+        result
     }
 
     /// Get the union of this and another list of packages.
@@ -154,6 +342,25 @@ impl Packages {
             .retain(|package| !old.iter().any(|old_package| package == old_package));
     }
 
+    /// The packages in this list that aren't in the other list.
+    pub fn difference(&self, other: &Packages) -> Packages {
+        Packages(
+            self.0
+                .iter()
+                .filter(|package| !other.contains(package))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Only keep the packages for which the predicate returns true.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Package) -> bool,
+    {
+        self.0.retain(f);
+    }
+
     /// Remove all duplicate packages.
     pub fn dedup(&mut self) {
         self.0.dedup();
@@ -169,6 +376,47 @@ impl Packages {
     }
 }
 
+/// The packages caught from a line with the possible problems found while parsing it.
+#[derive(Debug, Default, Clone)]
+pub struct ParseResult {
+    pub packages: Packages,
+    pub warnings: Vec<ParseWarning>,
+}
+
+impl ParseResult {
+    /// Move all packages and warnings from another result into this one.
+    pub fn append(&mut self, other: &mut ParseResult) {
+        self.packages.0.append(&mut other.packages.0);
+        self.warnings.append(&mut other.warnings);
+    }
+}
+
+impl IntoIterator for ParseResult {
+    type Item = Package;
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packages.into_iter()
+    }
+}
+
+/// Split a line into the separate commands.
+pub fn split_commands(line: &str) -> impl Iterator<Item = &str> {
+    line.split([';', '|', '&', '\r', '\n'])
+}
+
+/// Split a line into the separate commands with the package managers matching them.
+fn split_line(line: &str) -> impl Iterator<Item = (&str, PackageManager)> {
+    // First we split the line into separating characters
+    split_commands(line)
+        // Then try to find the proper package manager for each line, this also filters out
+        // lines that are not related to the package manager
+        .flat_map(|line| {
+            // Attempt to find a matching package managers with the line
+            PackageManager::from_line_iter(line).map(move |manager| (line, manager))
+        })
+}
+
 impl IntoIterator for Packages {
     type Item = Package;
     type IntoIter = std::vec::IntoIter<Self::Item>;
@@ -191,3 +439,273 @@ impl From<Vec<Package>> for Packages {
         Packages(x)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalFile, Package, Packages};
+    use crate::package_manager::{
+        Apt, ArgumentKind, Cargo, PackageManager, PackageManagerTrait, Pacman, ParseWarning, Pip,
+    };
+    use itertools::Itertools;
+    use proptest::{collection::vec, prelude::*, sample::select};
+    use std::{fs, path::Path};
+    use strum::IntoEnumIterator;
+
+    /// All commands of all package managers.
+    fn all_commands() -> Vec<&'static str> {
+        PackageManager::iter()
+            .flat_map(|manager| manager.commands())
+            .collect()
+    }
+
+    /// An argument that's not a flag and doesn't contain any package manager command.
+    fn word() -> impl Strategy<Value = String> {
+        "[a-z0-9][a-z0-9.+_/:@-]{0,12}".prop_filter("contains a package manager command", |word| {
+            !all_commands().iter().any(|command| word.contains(command))
+        })
+    }
+
+    /// A flag with an optional value.
+    fn flag() -> impl Strategy<Value = String> {
+        "--?[a-zA-Z]{1,10}(=[a-z0-9]{1,4})?"
+    }
+
+    /// The start of an install command for any package manager.
+    fn install_prefix() -> impl Strategy<Value = (PackageManager, String)> {
+        select(
+            PackageManager::iter()
+                .flat_map(|manager| {
+                    install_commands(manager)
+                        .into_iter()
+                        .map(move |prefix| (manager, prefix))
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// The start of an install command with a flag that invalidates it.
+    fn invalidated_prefix() -> impl Strategy<Value = (PackageManager, String, &'static str)> {
+        select(
+            PackageManager::iter()
+                .flat_map(|manager| {
+                    install_commands(manager)
+                        .into_iter()
+                        .cartesian_product(manager.invalidating_flags())
+                        .map(move |(prefix, flag)| (manager, prefix, flag))
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Every combination of command and subcommand of a package manager.
+    fn install_commands(manager: PackageManager) -> Vec<String> {
+        manager
+            .commands()
+            .into_iter()
+            .cartesian_product(manager.sub_commands())
+            .map(|(command, sub_command)| format!("{} {}", command, sub_command))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_from_line_arbitrary(line in "\\PC*") {
+            for package in Packages::parse_line(&line).packages.iter() {
+                prop_assert!(line.contains(package.name()));
+            }
+            LocalFile::from_line(&line);
+        }
+
+        #[test]
+        fn prop_from_line_install(
+            (_, prefix) in install_prefix(),
+            args in vec(prop_oneof![word(), flag()], 0..8),
+        ) {
+            let line = format!("sudo {} {}", prefix, args.join(" "));
+
+            for package in Packages::parse_line(&line).packages.iter() {
+                prop_assert!(line.contains(package.name()));
+            }
+        }
+
+        #[test]
+        fn prop_invalidating_flags(
+            (manager, prefix, flag) in invalidated_prefix(),
+            before in vec(word(), 0..4),
+            after in vec(word(), 0..4),
+        ) {
+            let line = format!("{} {} {} {}", prefix, before.join(" "), flag, after.join(" "));
+
+            prop_assert!(manager.catch(&line).packages.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_history_fixtures() {
+        let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join("history");
+
+        for entry in fs::read_dir(fixtures_dir).unwrap() {
+            let path = entry.unwrap().path();
+            let contents = fs::read_to_string(&path).unwrap();
+
+            for line in contents.lines().filter(|line| !line.starts_with('#')) {
+                let (command, expected) = line
+                    .split_once(" =>")
+                    .expect("fixture line is missing \"=>\"");
+
+                let caught = Packages::parse_line(command)
+                    .packages
+                    .iter()
+                    .map(|package| package.full_command())
+                    .join(", ");
+                assert_eq!(caught, expected.trim(), "{:?}: \"{}\"", path, command);
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_files() {
+        let files = LocalFile::from_line("sudo apt install ./foo.deb test");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].kind(), ArgumentKind::LocalPath);
+
+        let files = LocalFile::from_line("sudo pacman -U pkg.tar.zst");
+        assert_eq!(files.len(), 1);
+
+        let files = LocalFile::from_line("pip install -r requirements.txt");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].kind(), ArgumentKind::RequirementsFile);
+
+        let files = LocalFile::from_line("cargo install --path .");
+        assert_eq!(files.len(), 1);
+        assert!(!files[0].can_be_copied());
+
+        let files = LocalFile::from_line("npm i -g ./tool");
+        assert_eq!(files.len(), 1);
+
+        assert!(LocalFile::from_line("sudo apt install test").is_empty());
+    }
+
+    #[test]
+    fn test_requirements() {
+        let path =
+            std::env::temp_dir().join(format!("emplace-requirements-{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            indoc::indoc! {"
+                # Comment
+                -r other.txt
+                requests>=2.0 # HTTP
+                black[d]==23.1
+                git+https://github.com/psf/black
+                pkg @ https://example.com/pkg-1.0.tar.gz
+            "},
+        )
+        .unwrap();
+
+        let file = LocalFile::new(
+            PackageManager::from(Pip),
+            ArgumentKind::RequirementsFile,
+            path.to_string_lossy().to_string(),
+            vec![],
+        );
+        let names = file
+            .requirements()
+            .unwrap()
+            .iter()
+            .map(|package| package.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["requests", "black"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_install_args() {
+        let package = Package::artifact(
+            PackageManager::from(Apt),
+            "artifacts/my package_1.0_amd64.deb".to_string(),
+            vec![],
+        );
+        assert_eq!(
+            package.install_args(),
+            [
+                "sudo",
+                "apt-get",
+                "install",
+                "-y",
+                "./artifacts/my package_1.0_amd64.deb"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_warnings() {
+        let result = Packages::parse_line("sudo apt install --target-release bp test $PKG");
+        assert_eq!(result.packages.len(), 2);
+        assert_eq!(
+            result.warnings,
+            vec![
+                ParseWarning::UnknownFlag {
+                    manager: PackageManager::from(Apt),
+                    flag: "--target-release".to_string(),
+                    package: "bp".to_string(),
+                },
+                ParseWarning::SkippedToken {
+                    manager: PackageManager::from(Apt),
+                    token: "$PKG".to_string(),
+                },
+            ]
+        );
+
+        // Known boolean flags and invalidated commands don't warn
+        assert!(Packages::parse_line("sudo apt install -y test")
+            .warnings
+            .is_empty());
+        assert!(
+            Packages::parse_line("sudo apt install --dry-run --foo test $PKG")
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_difference() {
+        let package =
+            |name: &str| Package::new(PackageManager::from(Apt), name.to_string(), vec![]);
+        let old = Packages::from(vec![package("a"), package("b")]);
+        let new = Packages::from(vec![package("b"), package("c")]);
+
+        assert_eq!(*new.difference(&old), vec![package("c")]);
+        assert_eq!(*old.difference(&new), vec![package("a")]);
+        assert!(old.difference(&old).is_empty());
+    }
+
+    #[test]
+    fn test_installed_name() {
+        let artifact = |manager, path: &str| Package::artifact(manager, path.to_string(), vec![]);
+
+        assert_eq!(
+            artifact(PackageManager::from(Apt), "artifacts/foo_1.0_amd64.deb").installed_name(),
+            "foo"
+        );
+        assert_eq!(
+            artifact(
+                PackageManager::from(Pacman),
+                "artifacts/foo-bar-1.0-1-x86_64.pkg.tar.zst"
+            )
+            .installed_name(),
+            "foo-bar"
+        );
+        assert_eq!(
+            artifact(PackageManager::from(Pip), "artifacts/tool.whl").installed_name(),
+            "tool"
+        );
+        assert_eq!(
+            Package::new(PackageManager::from(Cargo), "test".to_string(), vec![]).installed_name(),
+            "test"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -38,14 +38,22 @@ impl PackageManagerTrait for Apt {
     }
 
     fn invalidating_flags(self) -> Vec<&'static str> {
+        vec!["-s", "--simulate", "--dry-run", "-d", "--download-only"]
+    }
+
+    fn path_flags(self) -> Vec<PathFlag> {
         vec![]
     }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("apt-get install -y")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Apt;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -69,6 +77,10 @@ mod tests {
         catch!(PackageManager::from(Apt), "sudo apt test test2" => ());
         catch!(PackageManager::from(Apt), "sudo apt install -f" => ());
         catch!(PackageManager::from(Apt), "sudo bapt install test" => ());
+        catch!(PackageManager::from(Apt), "sudo apt install ./test.deb" => ());
+        catch!(PackageManager::from(Apt), "sudo apt install -s test" => ());
+        catch!(PackageManager::from(Apt), "sudo apt install --download-only test" => ());
+        catch!(PackageManager::from(Apt), "sudo apt install /tmp/test.deb test" => "test");
     }
 
     #[test]
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -43,7 +43,7 @@ impl PackageManagerTrait for Cargo {
 
     fn capture_flags(self) -> Vec<CaptureFlag> {
         vec![
-            CaptureFlag::Single("--git"),
+            CaptureFlag::PackageValue("--git"),
             CaptureFlag::DynamicValue("--branch"),
             CaptureFlag::Single("+nightly"),
             CaptureFlag::Single("+stable"),
@@ -54,14 +54,22 @@ impl PackageManagerTrait for Cargo {
     }
 
     fn invalidating_flags(self) -> Vec<&'static str> {
-        vec!["--path"]
+        vec!["--list"]
+    }
+
+    fn path_flags(self) -> Vec<PathFlag> {
+        vec![PathFlag::Local("--path")]
+    }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        None
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Cargo;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -82,11 +90,12 @@ mod tests {
         catch!(PackageManager::from(Cargo), "cargo uninstall test test2" => ());
         catch!(PackageManager::from(Cargo), "cargo install ." => ());
         catch!(PackageManager::from(Cargo), "cargo install --path test" => ());
+        catch!(PackageManager::from(Cargo), "cargo install --list" => ());
         catch!(PackageManager::from(Cargo), "cargo binstall test" => ());
 
         // Flags that should be captured
         catch!(PackageManager::from(Cargo), "cargo install --git https://test.com/test.git" => "https://test.com/test.git" ["--git"]);
-        catch!(PackageManager::from(Cargo), "cargo install --git https://test.com/test.git --branch 3.x" => "https://test.com/test.git" ["--git", "--branch 3.x"]);
+        catch!(PackageManager::from(Cargo), "cargo install --git https://test.com/test.git --branch 3.x" => "https://test.com/test.git" ["--branch 3.x", "--git"]);
         catch!(PackageManager::from(Cargo), "cargo install +nightly test" => "test" ["+nightly"]);
     }
 }
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -59,7 +59,7 @@ impl PackageManagerTrait for CargoBinstall {
 
     fn capture_flags(self) -> Vec<CaptureFlag> {
         vec![
-            CaptureFlag::Single("--git"),
+            CaptureFlag::PackageValue("--git"),
             CaptureFlag::DynamicValue("--version"),
             CaptureFlag::DynamicValue("--targets"),
         ]
@@ -68,12 +68,20 @@ impl PackageManagerTrait for CargoBinstall {
     fn invalidating_flags(self) -> Vec<&'static str> {
         vec![]
     }
+
+    fn path_flags(self) -> Vec<PathFlag> {
+        vec![]
+    }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::CargoBinstall;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -40,14 +40,22 @@ impl PackageManagerTrait for Dnf {
     }
 
     fn invalidating_flags(self) -> Vec<&'static str> {
+        vec!["--downloadonly"]
+    }
+
+    fn path_flags(self) -> Vec<PathFlag> {
         vec![]
     }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("dnf install -y")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Dnf;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -70,5 +78,6 @@ mod tests {
         // Ignore
         catch!(PackageManager::from(Dnf), "sudo dnf test test2" => ());
         catch!(PackageManager::from(Dnf), "sudo dnf install -f" => ());
+        catch!(PackageManager::from(Dnf), "sudo dnf install --downloadonly test" => ());
     }
 }
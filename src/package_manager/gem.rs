@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -63,12 +63,20 @@ impl PackageManagerTrait for Gem {
     fn invalidating_flags(self) -> Vec<&'static str> {
         vec![]
     }
+
+    fn path_flags(self) -> Vec<PathFlag> {
+        vec![]
+    }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("gem install")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Gem;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
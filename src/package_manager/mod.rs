@@ -49,13 +49,14 @@ pub use zypper::Zypper;
 
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use strum_macros::EnumIter;
+use strum_macros::{EnumIter, IntoStaticStr};
 
 /// Enum containing all package managers.
 ///
 /// The actual functions are implemented in `src/package_manager_impl.rs`.
 #[enum_dispatch::enum_dispatch]
-#[derive(Debug, Copy, Clone, Eq, PartialEq, EnumIter, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, EnumIter, IntoStaticStr, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
 pub enum PackageManager {
     Apt,
     Brew,
@@ -114,6 +115,12 @@ pub trait PackageManagerTrait {
 
     /// Flags that make a command with a subcommand that would be caught otherwise be ignored.
     fn invalidating_flags(self) -> Vec<&'static str>;
+
+    /// Flags with a value that points to a file instead of a package name.
+    fn path_flags(self) -> Vec<PathFlag>;
+
+    /// Command that's used to install a package from a file, if supported.
+    fn install_local_command(self) -> Option<&'static str>;
 }
 
 /// Different ways in which a set of flags can be captured.
@@ -125,6 +132,83 @@ pub enum CaptureFlag {
     SetValue(&'static str, &'static str),
     /// A flag argument with a single dynamic value.
     DynamicValue(&'static str),
+    /// A flag argument that takes the package as its value, it's kept right before the package.
+    PackageValue(&'static str),
+}
+
+/// Different flags which have a file as a value.
+#[derive(Debug, Copy, Clone)]
+pub enum PathFlag {
+    /// The value is a local file or directory to install.
+    Local(&'static str),
+    /// The value is a file with a list of packages to install.
+    Requirements(&'static str),
+}
+
+/// How an argument of an install command is interpreted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArgumentKind {
+    /// Name of a package from the registry of the package manager.
+    Package,
+    /// A remote location, like a git repository.
+    Url,
+    /// A file or directory on this machine.
+    LocalPath,
+    /// A file containing a list of packages, like `requirements.txt`.
+    RequirementsFile,
+}
+
+/// How a single token after the subcommand is interpreted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Token {
+    /// An argument that's installed.
+    Argument(ArgumentKind),
+    /// An argument that's skipped because it doesn't look like a package.
+    Skipped,
+    /// A flag that's ignored.
+    Flag,
+    /// The value of a known flag, which is skipped.
+    FlagValue,
+    /// A flag or its value that's caught and added to the packages.
+    CapturedFlag,
+    /// A flag which has a file as value.
+    PathFlag,
+    /// A flag that causes the whole command to be ignored.
+    InvalidatingFlag,
+}
+
+/// The result of parsing a single package manager command.
+#[derive(Debug, Default, Clone)]
+pub struct CommandParse {
+    /// The command that invoked the package manager.
+    pub command: String,
+    /// The subcommand that's removed, nothing is parsed when it's not found.
+    pub sub_command: Option<&'static str>,
+    /// All tokens after the subcommand with how they are interpreted.
+    pub tokens: Vec<(String, Token)>,
+    /// The flags that are caught and added to every package.
+    pub flags: Vec<String>,
+}
+
+/// A possible problem found while parsing a command.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseWarning {
+    /// A flag that's not known, the package after it might actually be its value.
+    UnknownFlag {
+        manager: PackageManager,
+        flag: String,
+        package: String,
+    },
+    /// A token that's skipped because it doesn't look like a package.
+    SkippedToken {
+        manager: PackageManager,
+        token: String,
+    },
+    /// Multiple package managers caught packages from the same command.
+    AmbiguousManagers {
+        command: String,
+        managers: Vec<PackageManager>,
+    },
 }
 
 /// The way a package is checked if it's installed.
@@ -140,10 +224,7 @@ pub enum PackageInstalledMethod {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        catch,
-        package_manager::{apt::Apt, PackageManager},
-    };
+    use crate::package_manager::{apt::Apt, PackageManager};
 
     #[test]
     fn test_empty() {
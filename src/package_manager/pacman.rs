@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -14,7 +14,7 @@ impl PackageManagerTrait for Pacman {
     }
 
     fn sub_commands(self) -> Vec<&'static str> {
-        vec!["-S"]
+        vec!["-S", "-U"]
     }
 
     fn install_command(self) -> &'static str {
@@ -63,14 +63,22 @@ impl PackageManagerTrait for Pacman {
     }
 
     fn invalidating_flags(self) -> Vec<&'static str> {
+        vec!["-p", "--print", "-w", "--downloadonly"]
+    }
+
+    fn path_flags(self) -> Vec<PathFlag> {
         vec![]
     }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("pacman -U --noconfirm")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Pacman;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -91,5 +99,9 @@ mod tests {
 
         // Ignore
         catch!(PackageManager::from(Pacman), "sudo pacman test test2" => ());
+        catch!(PackageManager::from(Pacman), "sudo pacman -S -w test" => ());
+        catch!(PackageManager::from(Pacman), "sudo pacman -S --downloadonly test" => ());
+        catch!(PackageManager::from(Pacman), "pacman -S -p test" => ());
+        catch!(PackageManager::from(Pacman), "pacman -S --print test" => ());
     }
 }
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -14,7 +14,7 @@ impl PackageManagerTrait for Paru {
     }
 
     fn sub_commands(self) -> Vec<&'static str> {
-        vec!["-S"]
+        vec!["-S", "-U"]
     }
 
     fn install_command(self) -> &'static str {
@@ -95,12 +95,20 @@ impl PackageManagerTrait for Paru {
     fn invalidating_flags(self) -> Vec<&'static str> {
         vec![]
     }
+
+    fn path_flags(self) -> Vec<PathFlag> {
+        vec![]
+    }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("paru -U --noconfirm")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Paru;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -39,14 +39,27 @@ impl PackageManagerTrait for Pip {
     }
 
     fn invalidating_flags(self) -> Vec<&'static str> {
-        vec!["-r"]
+        vec!["--dry-run"]
+    }
+
+    fn path_flags(self) -> Vec<PathFlag> {
+        vec![
+            PathFlag::Requirements("-r"),
+            PathFlag::Requirements("--requirement"),
+            PathFlag::Local("-e"),
+            PathFlag::Local("--editable"),
+        ]
+    }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("pip install -q")
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Pip;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -67,5 +80,10 @@ mod tests {
         catch!(PackageManager::from(Pip), "sudo pip3 install test test2" => ());
         catch!(PackageManager::from(Pip), "sudo pip test test2" => ());
         catch!(PackageManager::from(Pip), "pip install -r requirements.txt" => ());
+        catch!(PackageManager::from(Pip), "pip install -e ." => ());
+        catch!(PackageManager::from(Pip), "pip install --dry-run test" => ());
+
+        // Requirements files aren't packages
+        catch!(PackageManager::from(Pip), "pip install -r requirements.txt test" => "test");
     }
 }
@@ -1,4 +1,4 @@
-use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait};
+use super::{CaptureFlag, PackageInstalledMethod, PackageManagerTrait, PathFlag};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -39,14 +39,27 @@ impl PackageManagerTrait for Pip3 {
     }
 
     fn invalidating_flags(self) -> Vec<&'static str> {
-        vec!["-r"]
+        vec!["--dry-run"]
+    }
+
+    fn path_flags(self) -> Vec<PathFlag> {
+        vec![
+            PathFlag::Requirements("-r"),
+            PathFlag::Requirements("--requirement"),
+            PathFlag::Local("-e"),
+            PathFlag::Local("--editable"),
+        ]
+    }
+
+    fn install_local_command(self) -> Option<&'static str> {
+        Some("pip3 install -q")
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Pip3;
-    use crate::{catch, package_manager::PackageManager};
+    use crate::package_manager::PackageManager;
 
     #[test]
     fn test_package_manager() {
@@ -67,5 +80,6 @@ mod tests {
         catch!(PackageManager::from(Pip3), "sudo pip install test test2" => ());
         catch!(PackageManager::from(Pip3), "sudo pip3 test test2" => ());
         catch!(PackageManager::from(Pip3), "pip3 install -r requirements.txt" => ());
+        catch!(PackageManager::from(Pip3), "pip3 install --dry-run test" => ());
     }
 }
@@ -1,14 +1,60 @@
 use crate::{
-    package::Package,
-    package_manager::{CaptureFlag, PackageInstalledMethod, PackageManager, PackageManagerTrait},
+    package::{LocalFile, Package, ParseResult},
+    package_manager::{
+        ArgumentKind, CaptureFlag, CommandParse, PackageInstalledMethod, PackageManager,
+        PackageManagerTrait, ParseWarning, PathFlag, Token,
+    },
 };
 use anyhow::{Context, Result};
-use itertools::iproduct;
+use itertools::{iproduct, Itertools};
 use run_script::ScriptOptions;
-use std::{env::split_paths, iter::Peekable, path::PathBuf};
+use std::{
+    env::split_paths,
+    fmt::{self, Display, Formatter},
+    iter::Peekable,
+    path::PathBuf,
+};
 use strum::IntoEnumIterator;
 
+/// Arguments starting with these point to a file or directory on this machine.
+const LOCAL_PATH_PREFIXES: [&str; 6] = ["./", "../", "/", "~", ".\\", "..\\"];
+
+/// Flags of package managers that are known not to take a value, so they don't cause warnings.
+const COMMON_BOOLEAN_FLAGS: [&str; 18] = [
+    "-y",
+    "--yes",
+    "-q",
+    "-qq",
+    "--quiet",
+    "-v",
+    "--verbose",
+    "-f",
+    "--force",
+    "-g",
+    "--global",
+    "-U",
+    "--upgrade",
+    "--user",
+    "--locked",
+    "--needed",
+    "--noconfirm",
+    "--no-install-recommends",
+];
+
+/// Arguments ending with these are package files on this machine.
+const LOCAL_FILE_EXTENSIONS: [&str; 11] = [
+    ".deb", ".rpm", ".tar.zst", ".tar.xz", ".tar.gz", ".tgz", ".pkg", ".whl", ".gem", ".snap",
+    ".nupkg",
+];
+
 impl PackageManager {
+    /// Find the package manager by its name, like `Pip`, `cargo-binstall` or `CargoBinstall`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalize = |name: &str| name.replace(['-', '_'], "").to_lowercase();
+
+        Self::iter().find(|manager| normalize(manager.into()) == normalize(name))
+    }
+
     /// Whether the line contains a package manager.
     pub fn detects_line(line: &str) -> bool {
         Self::single_from_line(line).is_some()
@@ -44,7 +90,7 @@ impl PackageManager {
 
     /// Check whether a package is already installed.
     pub fn package_is_installed(self, package: &Package) -> Result<bool> {
-        match self.is_installed(package.name()) {
+        match self.is_installed(package.installed_name()) {
             PackageInstalledMethod::Script(script) => {
                 // Run the installation script
                 let mut options = ScriptOptions::new();
@@ -76,112 +122,218 @@ impl PackageManager {
             .any(|x| x)
     }
 
-    /// Extract the packages from the line.
-    pub fn catch(self, line: &str) -> Vec<Package> {
-        // Try all different commands
-        self.os_commands()
+    /// Extract the packages from the line with the warnings that occurred while parsing it.
+    pub fn catch(self, line: &str) -> ParseResult {
+        let parses = self.parse(line);
+
+        let packages: Vec<Package> = Self::catch_arguments(&parses)
+            .into_iter()
+            // Files on this machine can't be mirrored as packages
+            .filter(|(kind, _, _)| matches!(kind, ArgumentKind::Package | ArgumentKind::Url))
+            .map(|(_, name, flags)| Package::new(self, name, flags))
+            .collect();
+
+        let warnings = parses
             .iter()
-            .flat_map(|command| {
-                // If the command can't be found in this line just continue
-                if !Self::line_contains_command(line, command) {
-                    return vec![];
-                }
+            .flat_map(|parse| parse.warnings(self))
+            .collect();
+
+        ParseResult {
+            packages: packages.into(),
+            warnings,
+        }
+    }
+
+    /// Extract the files on this machine that are installed in the line.
+    pub fn catch_local_files(self, line: &str) -> Vec<LocalFile> {
+        Self::catch_arguments(&self.parse(line))
+            .into_iter()
+            .filter(|(kind, _, _)| {
+                matches!(
+                    kind,
+                    ArgumentKind::LocalPath | ArgumentKind::RequirementsFile
+                )
+            })
+            .map(|(kind, path, flags)| LocalFile::new(self, kind, path, flags))
+            .collect()
+    }
+
+    /// Extract all arguments from the parsed commands with how they are interpreted and the
+    /// caught flags.
+    fn catch_arguments(parses: &[CommandParse]) -> Vec<(ArgumentKind, String, Vec<String>)> {
+        parses
+            .iter()
+            // Ignore the whole command when a flag invalidates it
+            .filter(|parse| !parse.is_invalidated())
+            .flat_map(|parse| {
+                parse
+                    .tokens
+                    .iter()
+                    .filter_map(move |(value, token)| match token {
+                        Token::Argument(kind) => Some((*kind, value.clone(), parse.flags.clone())),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
 
+    /// Parse the line, keeping track of how each token after the subcommand is interpreted.
+    pub fn parse(self, line: &str) -> Vec<CommandParse> {
+        // Try all different commands
+        self.os_commands()
+            .into_iter()
+            // If the command can't be found in this line just continue
+            .filter(|command| Self::line_contains_command(line, command))
+            .filter_map(|command| {
                 // Get the part right of the package manager invocation
                 // The command has another space so lengthened versions of itself don't collide,
                 // for example 'apt' & 'apt-get'
-                match line.split(&format!("{} ", command)).nth(1) {
-                    Some(rest_of_line) => {
-                        // The resulting packages strings
-                        let mut package_strings = vec![];
-
-                        // A list of flags that we caught that we should keep track of
-                        let mut catched_flags = vec![];
-
-                        // Get the line with the subcommand removed
-                        let line_without_subcommand = self
-                            .sub_commands()
-                            .clone()
-                            .into_iter()
-                            .find_map(|sub_command| {
-                                // Use a space terminated command to ensure that only exactly the
-                                // subcommand is removed
-                                let sub_command_with_space_postfix = format!("{} ", sub_command);
-                                if rest_of_line.starts_with(&sub_command_with_space_postfix) {
-                                    Some(
-                                        rest_of_line[sub_command_with_space_postfix.len()..]
-                                            .to_string(),
-                                    )
-                                } else {
-                                    let sub_command_with_spaces =
-                                        format!(" {}", sub_command_with_space_postfix);
-                                    // Remove the full subcommand from the list
-                                    let split_vec: Vec<_> =
-                                        rest_of_line.split(&sub_command_with_spaces).collect();
-                                    if split_vec.len() == 1 {
-                                        // If nothing is split it means that the sub command is not
-                                        // found in the string
-                                        None
-                                    } else {
-                                        // Rejoin the string with a space where the full subcommand
-                                        // with spaces was
-                                        Some(split_vec.join(" "))
-                                    }
-                                }
-                            });
-
-                        // Return an empty array when no subcommand found
-                        let line_without_subcommand = match line_without_subcommand {
-                            Some(line_without_subcommand) => line_without_subcommand,
-                            None => return vec![],
-                        };
-
-                        // Convert the line into an iterator over all arguments delimited by
-                        // whitespace
-                        let mut args_iter =
-                            line_without_subcommand.split_ascii_whitespace().peekable();
-
-                        // Loop over the arguments handling flags in a special way
-                        while let Some(arg) = args_iter.next() {
-                            // Stop when a flag is found that invalidate the command
-                            if self.has_invalidating_flag(arg) {
-                                return vec![];
-                            }
+                // When the package manager command was the last word of the line there's nothing
+                let rest_of_line = line.split(&format!("{} ", command)).nth(1)?;
+
+                // Get the line with the subcommand removed
+                let (sub_command, line_without_subcommand) =
+                    match self.strip_sub_command(rest_of_line) {
+                        Some(stripped) => stripped,
+                        // Nothing to parse when no subcommand found
+                        None => {
+                            return Some(CommandParse {
+                                command,
+                                ..Default::default()
+                            })
+                        }
+                    };
+
+                // Every token with how it's interpreted
+                let mut tokens = vec![];
+
+                // A list of flags that we caught that we should keep track of
+                let mut catched_flags = vec![];
+
+                // Convert the line into an iterator over all arguments delimited by
+                // whitespace
+                let mut args_iter = line_without_subcommand.split_ascii_whitespace().peekable();
+
+                // Loop over the arguments handling flags in a special way
+                while let Some(arg) = args_iter.next() {
+                    // Stop when a flag is found that invalidate the command
+                    if self.has_invalidating_flag(arg) {
+                        tokens.push((arg.to_string(), Token::InvalidatingFlag));
+                        break;
+                    }
 
-                            let first_char = arg
-                                .chars()
-                                .next()
-                                .expect("Arg string is suddenly zero bytes");
+                    // Splitting on whitespace never results in empty arguments
+                    let first_char = match arg.chars().next() {
+                        Some(first_char) => first_char,
+                        None => continue,
+                    };
 
-                            if first_char == '-' || first_char == '+' {
-                                self.handle_capture_flags(arg, &mut args_iter, &mut catched_flags);
+                    if first_char == '-' || first_char == '+' {
+                        // The value of the flag is a file instead of a package
+                        if let Some(path_flag) = self
+                            .path_flags()
+                            .into_iter()
+                            .find(|path_flag| path_flag.flag() == arg)
+                        {
+                            tokens.push((arg.to_string(), Token::PathFlag));
+                            if let Some(path) = args_iter.next() {
+                                tokens.push((path.to_string(), Token::Argument(path_flag.kind())));
+                            }
+                            continue;
+                        }
 
-                                // If it's a flag containing an extra arguments besides it skip one
-                                if self.known_flags_with_values().contains(&arg) {
-                                    // Skip the next item
-                                    args_iter.next();
-                                    continue;
+                        match self.handle_capture_flags(arg, &mut args_iter) {
+                            Some((flag, value)) => {
+                                catched_flags.push(flag);
+                                tokens.push((arg.to_string(), Token::CapturedFlag));
+                                if let Some(value) = value {
+                                    tokens.push((value.to_string(), Token::CapturedFlag));
                                 }
-                            // Only match packages starting with no special characters
-                            } else if first_char.is_alphanumeric() {
-                                // We've found a package
-                                package_strings.push(arg.to_string());
                             }
+                            None => tokens.push((arg.to_string(), Token::Flag)),
                         }
 
-                        // Now convert it into actual packages
-                        package_strings
-                            .into_iter()
-                            .map(|name| Package::new(self, name, catched_flags.clone()))
-                            .collect()
+                        // If it's a flag containing an extra arguments besides it skip one
+                        if self.known_flags_with_values().contains(&arg) {
+                            // Skip the next item
+                            if let Some(value) = args_iter.next() {
+                                tokens.push((value.to_string(), Token::FlagValue));
+                            }
+                        }
+                    } else {
+                        match Self::classify_argument(arg) {
+                            // Only match packages starting with no special characters
+                            ArgumentKind::Package if !first_char.is_alphanumeric() => {
+                                tokens.push((arg.to_string(), Token::Skipped))
+                            }
+                            kind => tokens.push((arg.to_string(), Token::Argument(kind))),
+                        }
                     }
-                    // Package manager command was the last word of the line
-                    None => vec![],
                 }
+
+                // Flags taking the package as their value must be right before it
+                catched_flags.sort_by_key(|flag| {
+                    self.capture_flags().into_iter().any(|capture| {
+                        matches!(capture, CaptureFlag::PackageValue(package_flag) if package_flag == flag)
+                    })
+                });
+
+                Some(CommandParse {
+                    command,
+                    sub_command: Some(sub_command),
+                    tokens,
+                    flags: catched_flags,
+                })
             })
             .collect()
     }
 
+    /// Find the subcommand in the rest of the line and remove it.
+    fn strip_sub_command(self, rest_of_line: &str) -> Option<(&'static str, String)> {
+        self.sub_commands().into_iter().find_map(|sub_command| {
+            // Use a space terminated command to ensure that only exactly the subcommand is
+            // removed
+            let sub_command_with_space_postfix = format!("{} ", sub_command);
+            if rest_of_line.starts_with(&sub_command_with_space_postfix) {
+                Some((
+                    sub_command,
+                    rest_of_line[sub_command_with_space_postfix.len()..].to_string(),
+                ))
+            } else {
+                let sub_command_with_spaces = format!(" {}", sub_command_with_space_postfix);
+                // Remove the full subcommand from the list
+                let split_vec: Vec<_> = rest_of_line.split(&sub_command_with_spaces).collect();
+                if split_vec.len() == 1 {
+                    // If nothing is split it means that the sub command is not found in the
+                    // string
+                    None
+                } else {
+                    // Rejoin the string with a space where the full subcommand with spaces was
+                    Some((sub_command, split_vec.join(" ")))
+                }
+            }
+        })
+    }
+
+    /// Decide what an argument that's not a flag points to.
+    fn classify_argument(arg: &str) -> ArgumentKind {
+        if arg.contains("://") || arg.starts_with("git+") || arg.starts_with("git@") {
+            ArgumentKind::Url
+        } else if arg == "."
+            || arg == ".."
+            || LOCAL_PATH_PREFIXES
+                .iter()
+                .any(|prefix| arg.starts_with(prefix))
+            || LOCAL_FILE_EXTENSIONS
+                .iter()
+                .any(|extension| arg.ends_with(extension))
+        {
+            ArgumentKind::LocalPath
+        } else {
+            ArgumentKind::Package
+        }
+    }
+
     /// Get OS specific commands, add .exe & .cmd on Windows.
     #[cfg(target_os = "windows")]
     fn os_commands(&self) -> Vec<String> {
@@ -212,44 +364,43 @@ impl PackageManager {
     }
 
     /// Handle the iterator's flags using the different options as defined in the package managers.
+    ///
+    /// Returns the caught flag and the value that's taken from the iterator for it.
     fn handle_capture_flags<'a, I>(
         self,
         arg: &str,
         args_iter: &mut Peekable<I>,
-        catched_flags: &mut Vec<String>,
-    ) where
+    ) -> Option<(String, Option<&'a str>)>
+    where
         I: Iterator<Item = &'a str>,
     {
         // Find the matching flags from the capture_flags function
-        let capture = match self
+        let capture = self
             .capture_flags()
             .into_iter()
-            .find(|capture| capture.flag() == arg)
-        {
-            Some(capture) => capture,
-            None => return,
-        };
+            .find(|capture| capture.flag() == arg)?;
 
         match capture {
-            CaptureFlag::Single(flag) => {
+            CaptureFlag::Single(flag) | CaptureFlag::PackageValue(flag) => {
                 // Just a single flag, add it to the list
-                catched_flags.push(flag.to_string());
+                Some((flag.to_string(), None))
             }
             CaptureFlag::SetValue(flag, value) => {
                 // The value is set and must match
-                if let Some(next_arg) = args_iter.peek() {
-                    if &value == next_arg {
-                        catched_flags.push(format!("{} {}", flag, value));
-                        // We've looked at the next item so we should also skip it
-                        args_iter.next();
-                    }
+                if args_iter.peek() == Some(&value) {
+                    // We've looked at the next item so we should also skip it
+                    let next_arg = args_iter.next();
+
+                    Some((format!("{} {}", flag, value), next_arg))
+                } else {
+                    None
                 }
             }
             CaptureFlag::DynamicValue(flag) => {
                 // The flag matches and the next value is dynamic so just take that
-                if let Some(next_arg) = args_iter.next() {
-                    catched_flags.push(format!("{} {}", flag, next_arg))
-                }
+                let next_arg = args_iter.next()?;
+
+                Some((format!("{} {}", flag, next_arg), Some(next_arg)))
             }
         }
     }
@@ -274,13 +425,109 @@ impl CaptureFlag {
             CaptureFlag::Single(flag) => flag,
             CaptureFlag::SetValue(flag, _) => flag,
             CaptureFlag::DynamicValue(flag) => flag,
+            CaptureFlag::PackageValue(flag) => flag,
+        }
+    }
+}
+
+impl CommandParse {
+    /// Whether a flag makes sure nothing in the command is caught.
+    pub fn is_invalidated(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|(_, token)| *token == Token::InvalidatingFlag)
+    }
+
+    /// Find the tokens which might not be handled correctly.
+    pub fn warnings(&self, manager: PackageManager) -> Vec<ParseWarning> {
+        // Nothing is caught so nothing can go wrong
+        if self.is_invalidated() {
+            return vec![];
+        }
+
+        self.tokens
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (value, token))| match token {
+                Token::Skipped => Some(ParseWarning::SkippedToken {
+                    manager,
+                    token: value.clone(),
+                }),
+                // A flag we don't know anything about that's directly followed by a package
+                Token::Flag
+                    if !value.contains('=') && !COMMON_BOOLEAN_FLAGS.contains(&value.as_str()) =>
+                {
+                    match self.tokens.get(index + 1) {
+                        Some((package, Token::Argument(ArgumentKind::Package))) => {
+                            Some(ParseWarning::UnknownFlag {
+                                manager,
+                                flag: value.clone(),
+                                package: package.clone(),
+                            })
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::UnknownFlag {
+                manager,
+                flag,
+                package,
+            } => write!(
+                f,
+                "unknown flag `{}` for {} might take a value, `{}` is caught as a package",
+                flag,
+                manager.full_name(),
+                package
+            ),
+            ParseWarning::SkippedToken { manager, token } => write!(
+                f,
+                "token `{}` for {} skipped, it doesn't look like a package",
+                token,
+                manager.full_name()
+            ),
+            ParseWarning::AmbiguousManagers { command, managers } => write!(
+                f,
+                "`{}` matches multiple package managers: {}",
+                command,
+                managers
+                    .iter()
+                    .map(|manager| manager.full_name())
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl PathFlag {
+    /// Extract the flag which is always there.
+    pub fn flag(self) -> &'static str {
+        match self {
+            PathFlag::Local(flag) => flag,
+            PathFlag::Requirements(flag) => flag,
+        }
+    }
+
+    /// How the value of the flag is interpreted.
+    pub fn kind(self) -> ArgumentKind {
+        match self {
+            PathFlag::Local(_) => ArgumentKind::LocalPath,
+            PathFlag::Requirements(_) => ArgumentKind::RequirementsFile,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::package_manager::PackageManager;
+    use crate::package_manager::{Apt, ArgumentKind, PackageManager, Token};
 
     #[test]
     fn test_detect() {
@@ -294,6 +541,47 @@ mod tests {
         assert!(PackageManager::detects_line("\n\tapt install test"));
     }
 
+    #[test]
+    fn test_parse() {
+        let parses =
+            PackageManager::from(Apt).parse("sudo apt install -c file -t experimental test");
+        assert_eq!(parses.len(), 1);
+        assert_eq!(parses[0].command, "apt");
+        assert_eq!(parses[0].sub_command, Some("install"));
+        assert_eq!(
+            parses[0].tokens,
+            vec![
+                ("-c".to_string(), Token::Flag),
+                ("file".to_string(), Token::FlagValue),
+                ("-t".to_string(), Token::CapturedFlag),
+                ("experimental".to_string(), Token::CapturedFlag),
+                ("test".to_string(), Token::Argument(ArgumentKind::Package)),
+            ]
+        );
+        assert_eq!(parses[0].flags, vec!["-t experimental".to_string()]);
+
+        let parses = PackageManager::from(Apt).parse("sudo apt remove test");
+        assert_eq!(parses[0].sub_command, None);
+        assert!(parses[0].tokens.is_empty());
+    }
+
+    #[test]
+    fn test_classify_argument() {
+        let classify = PackageManager::classify_argument;
+
+        assert_eq!(classify("test"), ArgumentKind::Package);
+        assert_eq!(classify("github.com/test/test"), ArgumentKind::Package);
+        assert_eq!(classify("https://test.com/test.git"), ArgumentKind::Url);
+        assert_eq!(classify("git+https://test.com/test.git"), ArgumentKind::Url);
+        assert_eq!(classify("."), ArgumentKind::LocalPath);
+        assert_eq!(classify("./foo.deb"), ArgumentKind::LocalPath);
+        assert_eq!(classify("/tmp/tool"), ArgumentKind::LocalPath);
+        assert_eq!(
+            classify("pkg-1.0-1-x86_64.pkg.tar.zst"),
+            ArgumentKind::LocalPath
+        );
+    }
+
     #[cfg(target_os = "windows")]
     #[test]
     fn test_detect_windows() {
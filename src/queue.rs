@@ -0,0 +1,211 @@
+use crate::{
+    lock::{self, FileLock, LOCK_TIMEOUT},
+    package::Packages,
+};
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Changes that couldn't be shared yet because the remote wasn't reachable.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Queue {
+    /// Where the queue is persisted.
+    #[serde(skip)]
+    path: PathBuf,
+    /// Messages of the commits that are made but haven't been pushed.
+    #[serde(default)]
+    pub commits: Vec<String>,
+    /// Packages that are caught while the repository couldn't be opened or that are waiting to be
+    /// mirrored in the background.
+    #[serde(default)]
+    pub packages: Packages,
+    /// Why synchronizing in the background failed, reported at the next prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Queue {
+    /// Load the queue of the repository directory.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::from_path(Self::path_for(dir))
+    }
+
+    /// Change the queue of the repository directory, making sure no other process changes it at
+    /// the same time.
+    pub fn modify<P, T, F>(dir: P, f: F) -> Result<T>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Queue) -> T,
+    {
+        Self::modify_timeout(dir, LOCK_TIMEOUT, f)
+    }
+
+    /// Change the queue of the repository directory, giving up when another process holds the
+    /// lock for longer than the timeout.
+    fn modify_timeout<P, T, F>(dir: P, timeout: Duration, f: F) -> Result<T>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Queue) -> T,
+    {
+        let _lock =
+            FileLock::acquire_timeout(Self::path_for(&dir).with_extension("lock"), timeout)?;
+
+        let mut queue = Self::load(dir)?;
+        let result = f(&mut queue);
+        queue.save()?;
+
+        Ok(result)
+    }
+
+    /// Load the queue from a file, an empty queue when it doesn't exist.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                ..Self::default()
+            });
+        }
+
+        let contents = fs::read_to_string(&path).context("reading queue file")?;
+        let queue = ron::de::from_str(&contents).context("deserializing queue file")?;
+
+        Ok(Self { path, ..queue })
+    }
+
+    /// Persist the queue, removing the file when nothing is pending.
+    pub fn save(&self) -> Result<()> {
+        if self.is_empty() && self.error.is_none() {
+            if self.path.exists() {
+                fs::remove_file(&self.path).context("removing empty queue file")?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("creating directory for queue file")?;
+        }
+
+        let contents = to_string_pretty(self, PrettyConfig::new().depth_limit(2))?;
+        fs::write(&self.path, contents).context("writing queue file")?;
+
+        Ok(())
+    }
+
+    /// Remember a commit that couldn't be pushed.
+    pub fn push_commit(&mut self, message: &str) {
+        self.commits.push(message.to_string());
+    }
+
+    /// Remember packages that couldn't be mirrored.
+    pub fn push_packages(&mut self, packages: &mut Packages) {
+        self.packages.merge(packages);
+    }
+
+    /// Forget the commits that couldn't be pushed, after they are pushed.
+    pub fn clear_commits(&mut self) {
+        self.commits.clear();
+    }
+
+    /// Take the packages that are waiting to be mirrored out of the queue.
+    pub fn take_packages(&mut self) -> Packages {
+        std::mem::replace(&mut self.packages, Packages::empty())
+    }
+
+    /// Amount of commits and packages that are pending.
+    pub fn len(&self) -> usize {
+        self.commits.len() + self.packages.len()
+    }
+
+    /// Whether nothing is pending.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the path where the queue of a repository directory is stored, every repository has its
+    /// own queue so the changes end up in the right one.
+    pub fn path_for<P: AsRef<Path>>(dir: P) -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(
+            dirs::cache_dir()
+                .expect("Could not find cache dir")
+                .join("emplace")
+                .join("queues")
+                .join(format!("{}.ron", lock::path_name(dir))),
+        )
+        .expect("Path is not valid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Queue;
+    use crate::{
+        lock::FileLock,
+        package::{Package, Packages},
+        package_manager::{Apt, PackageManager},
+    };
+    use std::{fs, time::Duration};
+
+    #[test]
+    fn test_persist() {
+        let path = std::env::temp_dir().join(format!("emplace-queue-{}.ron", std::process::id()));
+
+        let mut queue = Queue::from_path(&path).unwrap();
+        assert!(queue.is_empty());
+
+        queue.push_commit("Emplace - mirror package \"sudo apt install test\"");
+        queue.push_packages(&mut Packages::from(vec![Package::new(
+            PackageManager::from(Apt),
+            "test".to_string(),
+            vec![],
+        )]));
+        queue.save().unwrap();
+
+        let mut queue = Queue::from_path(&path).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.packages[0].name(), "test");
+
+        // An empty queue doesn't leave a file behind
+        queue.clear_commits();
+        assert_eq!(queue.take_packages().len(), 1);
+        queue.save().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_path_for() {
+        // Packages queued for one repository must not end up in another
+        assert_ne!(
+            Queue::path_for("/home/user/.local/share/emplace"),
+            Queue::path_for("/home/user/other-mirrors")
+        );
+        assert_eq!(Queue::path_for("/mirrors"), Queue::path_for("/mirrors"));
+    }
+
+    #[test]
+    fn test_modify_contended() {
+        let dir = std::env::temp_dir().join(format!("emplace-queue-dir-{}", std::process::id()));
+        let lock_path = Queue::path_for(&dir).with_extension("lock");
+
+        // Another process is changing the queue
+        let lock = FileLock::acquire_timeout(&lock_path, Duration::from_millis(200)).unwrap();
+        let result = Queue::modify_timeout(&dir, Duration::from_millis(200), |queue| {
+            queue.push_commit("Emplace - mirror package \"sudo apt install test\"")
+        });
+        assert!(result.is_err());
+        assert!(!Queue::path_for(&dir).exists());
+
+        drop(lock);
+        let len = Queue::modify_timeout(&dir, Duration::from_millis(200), |queue| queue.len());
+        assert_eq!(len.unwrap(), 0);
+
+        fs::remove_file(lock_path).unwrap();
+    }
+}
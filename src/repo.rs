@@ -1,100 +1,144 @@
-use crate::{config::Config, git, migrate::zero_two, package::Packages};
-use anyhow::{Context, Result};
-use log::debug;
-use ron::{
-    de,
-    ser::{to_string_pretty, PrettyConfig},
+use crate::{
+    config::Config,
+    lock::{FileLock, LOCK_TIMEOUT},
+    migrate::zero_two,
+    package::{LocalFile, Package, Packages},
+    package_manager::{PackageManager, PackageManagerTrait},
+    storage::{Revision, StorageBackend, StorageBackendTrait},
 };
-#[cfg(unix)]
-use std::os::unix::fs::symlink;
-#[cfg(not(unix))]
-use std::os::windows::fs::symlink_file as symlink;
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use log::debug;
+use ron::de;
 use std::{
-    fs::{self, File},
-    io::Read,
+    fs,
     path::{Path, PathBuf},
 };
+use strum::IntoEnumIterator;
+
+/// The packages that are added and removed in a version of the mirrors file.
+#[derive(Debug)]
+pub struct Change {
+    pub revision: Revision,
+    pub added: Packages,
+    pub removed: Packages,
+}
 
-/// Git repository where the emplace file lives.
+/// Storage where the emplace file lives.
 #[derive(Debug)]
 pub struct Repo {
-    config: Config,
-    path: PathBuf,
+    storage: StorageBackend,
+    /// File locked while the storage is changed, so multiple processes don't interfere.
+    lock_path: PathBuf,
+    /// Template for the subject of commit messages.
+    commit_template: Option<String>,
 }
 
 impl Repo {
     pub fn new(config: Config, pull_if_exists: bool) -> Result<Self> {
         debug!("Retrieving repository");
 
-        let repo_directory = config.repo_directory.clone();
-        let repo_url = config.repo.url.clone();
-        let repo_branch = config.repo.branch.clone();
-
-        let path = Path::new(&repo_directory);
-        let path_str = path.to_str().expect("Could not get directory").to_string();
+        let storage = StorageBackend::from_config(&config);
+        let lock_path = FileLock::path_for(storage.directory());
 
-        let repo_exists = path.join(".git").exists();
-        if repo_exists {
-            println!("Opening Emplace repo: \"{}\".", path_str);
+        let _lock = FileLock::acquire_timeout(&lock_path, LOCK_TIMEOUT)?;
+        storage.open(pull_if_exists).context("opening storage")?;
 
-            if pull_if_exists {
-                git::pull(&path, &repo_branch).context("pulling existing repo from config")?;
-            }
-        } else {
-            println!("Cloning Emplace repo \"{}\" to \"{}\".", repo_url, path_str);
-
-            fs::create_dir_all(path).context("creating new directory for repo")?;
-            git::clone_single_branch(&path, &repo_url, &repo_branch).context("cloning new repo")?;
-
-            // Create the emplace file if it doesn't exist
-            let emplace_file = config.full_file_path();
-            if !emplace_file.exists() {
-                // If the repo contains a configuration file create a symbolic link to that,
-                // otherwise create a new configuration file
-                let repo_config_file = path.join("emplace.toml");
-                if repo_config_file.exists() {
-                    // Create a symbolic link
-                    symlink(repo_config_file, &emplace_file)?;
-                } else {
-                    // Create a new configuration file
-                    let empty_packages = Packages::empty();
-                    let toml_string = to_string_pretty(&empty_packages, Repo::pretty_config())?;
-                    fs::write(&emplace_file, toml_string)?;
-                }
-            }
+        // Create the emplace mirrors if they don't exist
+        let emplace_file = storage.file_path();
+        let emplace_directory = storage.mirrors_directory();
+        if !storage.is_read_only() && !emplace_file.exists() && !emplace_directory.exists() {
+            fs::create_dir_all(&emplace_directory).context("creating Emplace mirrors directory")?;
         }
 
         Ok(Repo {
-            config,
-            path: path.to_path_buf(),
+            storage,
+            lock_path,
+            commit_template: config.repo.commit_message,
         })
     }
 
-    /// Perform a git pull on the repository.
+    /// Retrieve the latest changes of the storage.
     pub fn pull(&self) -> Result<()> {
-        let repo_branch = self.config.repo.branch.clone();
-        git::pull(&self.path, &repo_branch).context("pulling repository")?;
+        let _lock = self.lock()?;
 
-        Ok(())
+        self.storage.pull()
+    }
+
+    /// Share the changes that couldn't be shared before, only pulling when not pushing. Returns
+    /// whether the changes are shared.
+    pub fn sync(&self, rebase: bool, push: bool) -> Result<bool> {
+        let _lock = self.lock()?;
+
+        self.storage.sync(rebase, push)
+    }
+
+    /// Whether packages can't be mirrored to the storage.
+    pub fn is_read_only(&self) -> bool {
+        self.storage.is_read_only()
     }
 
     pub fn read(&self) -> Result<Packages> {
-        // Open the file
-        let mut file = File::open(self.config.full_file_path())
-            .context("failed opening Emplace mirrors file")?;
+        let _lock = self.lock()?;
+
+        self.read_unlocked()
+    }
+
+    /// Read the packages, the lock must already be taken.
+    fn read_unlocked(&self) -> Result<Packages> {
+        Repo::read_path(&self.storage.file_path(), &self.storage.mirrors_directory())
+    }
+
+    /// Read the packages from the mirrors directory and the single mirrors file.
+    ///
+    /// Both are read because a machine with an older version might still write the single file.
+    pub fn read_path(file: &Path, directory: &Path) -> Result<Packages> {
+        if !file.exists() && !directory.exists() {
+            bail!(
+                "failed opening Emplace mirrors, neither \"{}\" nor \"{}\" exists",
+                file.display(),
+                directory.display()
+            );
+        }
+
+        let mut packages = vec![];
+        if file.exists() {
+            let contents =
+                fs::read_to_string(file).context("reading packages string from repository")?;
+            packages.extend(Repo::parse(&contents)?.iter().cloned());
+        }
+
+        if directory.exists() {
+            let files = fs::read_dir(directory)
+                .context("reading Emplace mirrors directory")?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<Result<Vec<_>>>()?;
+            for path in files
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+                .sorted()
+            {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("reading mirrors file \"{}\"", path.display()))?;
+                packages.append(
+                    &mut Repo::parse_manager_file(&contents)
+                        .with_context(|| format!("reading mirrors file \"{}\"", path.display()))?,
+                );
+            }
+        }
 
-        // Read the contents
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .context("reading packages string from repository")?;
+        // Duplicates aren't removed, they are only removed when the mirrors are written
+        Ok(Packages::from(packages))
+    }
 
-        // Deserialize the file into the struct
-        match de::from_str(&contents) {
+    /// Deserialize the contents of a mirrors file.
+    fn parse(contents: &str) -> Result<Packages> {
+        match de::from_str(contents) {
             Ok(packages) => Ok(packages),
             // Deserializing failed, try to migrate from a previous version
             Err(err) => {
                 // Try to migrate from emplace version <= 0.2
-                if let Some(packages) = zero_two::try_migrate(&contents) {
+                if let Some(packages) = zero_two::try_migrate(contents) {
                     return Ok(packages);
                 }
 
@@ -104,61 +148,348 @@ impl Repo {
         }
     }
 
+    /// Deserialize the contents of a mirrors file of a single package manager.
+    fn parse_manager_file(contents: &str) -> Result<Vec<Package>> {
+        de::from_str(contents).context("deserializing packages from repository")
+    }
+
+    /// The packages added and removed by every version of the mirrors file, oldest first.
+    pub fn changes(&self) -> Result<Vec<Change>> {
+        let revisions = {
+            let _lock = self.lock()?;
+
+            self.storage.history()?
+        };
+
+        let mut previous = Packages::empty();
+        revisions
+            .into_iter()
+            .map(|revision| {
+                let mut packages = match &revision.contents {
+                    Some(contents) => Repo::parse(contents)
+                        .with_context(|| format!("reading commit {}", revision.commit.hash))?,
+                    None => Packages::empty(),
+                };
+                for contents in revision.directory.iter() {
+                    packages.merge(&mut Packages::from(
+                        Repo::parse_manager_file(contents)
+                            .with_context(|| format!("reading commit {}", revision.commit.hash))?,
+                    ));
+                }
+
+                // What changed in the oldest version of a shallow clone is unknown
+                let change = if revision.shallow {
+                    Change {
+                        added: Packages::empty(),
+                        removed: Packages::empty(),
+                        revision,
+                    }
+                } else {
+                    Change {
+                        added: packages.difference(&previous),
+                        removed: previous.difference(&packages),
+                        revision,
+                    }
+                };
+                previous = packages;
+
+                Ok(change)
+            })
+            .collect()
+    }
+
     pub fn mirror(&self, mut commands: Packages) -> Result<()> {
+        let _lock = self.lock()?;
+
         // Get the message first before the old stuff is added
-        let mut commit_msg = commands.commit_message();
+        let commit_msg = self.commit_message(
+            "mirror",
+            commands.commit_message(),
+            &commands,
+            &Packages::empty(),
+        );
 
-        let full_path = self.config.full_file_path();
-        if full_path.exists() {
-            // A file already exists, merge the existing one with the current one
-            let mut old: Packages = self.read()?;
+        if self.storage.file_path().exists() || self.storage.mirrors_directory().exists() {
+            // The mirrors already exist, merge the existing ones with the current one
+            let mut old: Packages = self.read_unlocked()?;
 
             // Merge it with the new one
             commands.merge(&mut old);
         }
 
-        // There's no file yet, just serialize everything and write it to a new file
-        let toml_string = to_string_pretty(&commands, Repo::pretty_config())?;
+        self.write(&commands)?;
 
-        fs::write(&full_path, toml_string)?;
+        self.storage.save(&commit_msg)
+    }
 
-        // Add the file to git
-        git::add_file(&self.path, &self.config.repo.file)?;
+    /// Copy a package file from this machine into the repository so it can be mirrored.
+    pub fn copy_artifact(&self, file: &LocalFile, package: &Package) -> Result<()> {
+        let _lock = self.lock()?;
 
-        // Check if there are other changes
-        if git::has_changes(&self.path)? {
-            commit_msg.push_str("\nIncluding changes of other files in the repository.");
-            git::add_all_files(&self.path)?;
+        if self.storage.is_read_only() {
+            bail!("storage is read-only, package files can't be copied");
         }
 
-        println!("Committing with message \"{}\".", commit_msg);
-        git::commit_all(&self.path, &commit_msg, false)?;
+        let artifact_path = self.storage.directory().join(package.name());
+        if let Some(artifact_dir) = artifact_path.parent() {
+            fs::create_dir_all(artifact_dir).context("creating directory for package files")?;
+        }
 
-        println!("Pushing to remote.");
-        git::push(&self.path)?;
+        fs::copy(file.resolved_path(), &artifact_path)
+            .context("copying package file into repository")?;
 
         Ok(())
     }
 
+    /// The directory of the repository.
+    pub fn path(&self) -> PathBuf {
+        self.storage.directory()
+    }
+
     pub fn clean(&self, commands: Packages) -> Result<()> {
+        let _lock = self.lock()?;
+
+        let removed = self.read_unlocked()?.difference(&commands);
+        let commit_msg = self.commit_message(
+            "clean",
+            "Emplace - clean packages".to_string(),
+            &Packages::empty(),
+            &removed,
+        );
+
         // Overwrite the file
-        let toml_string = to_string_pretty(&commands, Repo::pretty_config())?;
+        self.write(&commands)?;
+
+        self.storage.save(&commit_msg)
+    }
+
+    /// Revert the packages added and removed in a change, keeping everything changed after it.
+    pub fn undo(&self, change: &Change) -> Result<()> {
+        let _lock = self.lock()?;
+
+        let current = self.read_unlocked()?;
+
+        // Only revert what's still relevant, later changes might already have done it
+        let removed = Packages::from(
+            change
+                .added
+                .iter()
+                .filter(|package| current.contains(package))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        let added = change.removed.difference(&current);
+
+        let mut packages = current.difference(&removed);
+        packages.merge(&mut added.clone());
+
+        let hash = &change.revision.commit.hash;
+        let commit_msg = self.commit_message(
+            "undo",
+            format!("Emplace - undo {}", &hash[..7.min(hash.len())]),
+            &added,
+            &removed,
+        );
+
+        self.write(&packages)?;
+
+        self.storage.save(&commit_msg)
+    }
+
+    /// Add trailers to the commit message describing where the change comes from and which
+    /// packages are changed.
+    ///
+    /// The subject is replaced by the configured template when there is one.
+    fn commit_message(
+        &self,
+        action: &str,
+        subject: String,
+        added: &Packages,
+        removed: &Packages,
+    ) -> String {
+        let managers = added
+            .iter()
+            .chain(removed.iter())
+            .map(|package| package.source().full_name())
+            .unique()
+            .join(", ");
+        let host = gethostname::gethostname().to_string_lossy().to_string();
+
+        let subject = match &self.commit_template {
+            Some(template) => {
+                let packages = added
+                    .iter()
+                    .chain(removed.iter())
+                    .map(|package| package.full_command())
+                    .join(", ");
+
+                fill_template(template, action, &packages, &managers, &host)
+            }
+            None => subject,
+        };
+
+        let mut message = format!(
+            "{}\n\nEmplace-Host: {}\nEmplace-Managers: {}",
+            subject, host, managers
+        );
+        let trailers = added
+            .iter()
+            .map(|package| ("Added", package))
+            .chain(removed.iter().map(|package| ("Removed", package)));
+        for (change, package) in trailers {
+            message.push_str(&format!(
+                "\nEmplace-{}: {} ({})",
+                change,
+                package.full_command(),
+                package.source().full_name()
+            ));
+        }
+
+        message
+    }
+
+    /// Serialize the packages into the mirrors directory, converting the single mirrors file.
+    fn write(&self, commands: &Packages) -> Result<()> {
+        if self.storage.is_read_only() {
+            bail!("storage is read-only, packages can't be mirrored");
+        }
+
+        let directory = self.storage.mirrors_directory();
+        Repo::write_directory(&directory, commands)?;
+
+        // The packages of the single file are already merged into the directory
+        let file = self.storage.file_path();
+        if file.exists() || file.is_symlink() {
+            println!(
+                "Converting mirrors file \"{}\" to directory \"{}\".",
+                file.display(),
+                directory.display()
+            );
+
+            fs::remove_file(&file).context("removing converted mirrors file")?;
+        }
 
-        let full_path = self.config.full_file_path();
-        fs::write(full_path, toml_string)?;
+        Ok(())
+    }
+
+    /// Write a file per package manager, with every package sorted on a separate line so changes
+    /// from different machines rarely conflict.
+    pub fn write_directory(directory: &Path, commands: &Packages) -> Result<()> {
+        fs::create_dir_all(directory).context("creating mirrors directory")?;
+
+        for manager in PackageManager::iter() {
+            let path = directory.join(format!("{}.ron", <&str>::from(manager)));
+
+            let packages = commands
+                .iter()
+                .filter(|package| package.source() == manager)
+                .sorted()
+                .dedup()
+                .collect::<Vec<_>>();
+            if packages.is_empty() {
+                // Don't keep files of package managers that aren't used anymore
+                if path.exists() {
+                    fs::remove_file(&path).context("removing empty mirrors file")?;
+                }
 
-        let commit_msg = "Emplace - clean packages";
-        println!("Committing with message \"{}\".", commit_msg);
-        git::add_file(&self.path, &self.config.repo.file)?;
-        git::commit_all(&self.path, commit_msg, false)?;
+                continue;
+            }
+
+            let mut contents = "[\n".to_string();
+            for package in packages {
+                contents.push_str(&ron::ser::to_string_pretty(
+                    package,
+                    ron::ser::PrettyConfig::new().depth_limit(0),
+                )?);
+                contents.push_str(",\n");
+            }
+            contents.push_str("]\n");
 
-        println!("Pushing to remote.");
-        git::push(&self.path)?;
+            fs::write(&path, contents)
+                .with_context(|| format!("writing mirrors file \"{}\"", path.display()))?;
+        }
 
         Ok(())
     }
 
-    fn pretty_config() -> PrettyConfig {
-        PrettyConfig::new().depth_limit(2).indentor("".into())
+    /// Make sure no other emplace process uses the repository until the lock is dropped.
+    fn lock(&self) -> Result<FileLock> {
+        FileLock::acquire_timeout(&self.lock_path, LOCK_TIMEOUT)
+    }
+}
+
+/// Replace the placeholders in a commit message template.
+fn fill_template(
+    template: &str,
+    action: &str,
+    packages: &str,
+    managers: &str,
+    host: &str,
+) -> String {
+    template
+        .replace("{action}", action)
+        .replace("{packages}", packages)
+        .replace("{managers}", managers)
+        .replace("{host}", host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_manager::{Cargo, Pip};
+
+    #[test]
+    fn test_directory_layout() {
+        let directory =
+            std::env::temp_dir().join(format!("emplace-mirrors-{}.d", std::process::id()));
+        let file = directory.with_extension("ron");
+
+        let packages = Packages::from(vec![
+            Package::new(Cargo.into(), "b".to_string(), vec![]),
+            Package::new(Pip.into(), "c".to_string(), vec!["--user".to_string()]),
+            Package::new(Cargo.into(), "a".to_string(), vec![]),
+            Package::new(Cargo.into(), "a".to_string(), vec![]),
+        ]);
+        Repo::write_directory(&directory, &packages).unwrap();
+
+        // Every package is on a separate line, sorted and without duplicates
+        let cargo = fs::read_to_string(directory.join("cargo.ron")).unwrap();
+        assert_eq!(cargo.lines().count(), 4);
+        assert!(cargo.find("\"a\"").unwrap() < cargo.find("\"b\"").unwrap());
+        assert!(directory.join("pip.ron").exists());
+        assert_eq!(Repo::read_path(&file, &directory).unwrap().len(), 3);
+
+        // The single file is read together with the directory
+        fs::write(&file, r#"([(source: Pip, name: "d")])"#).unwrap();
+        assert_eq!(Repo::read_path(&file, &directory).unwrap().len(), 4);
+
+        // Files of package managers without packages are removed
+        Repo::write_directory(
+            &directory,
+            &Packages::from(vec![Package::new(Cargo.into(), "a".to_string(), vec![])]),
+        )
+        .unwrap();
+        assert!(!directory.join("pip.ron").exists());
+
+        fs::remove_file(&file).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_fill_template() {
+        assert_eq!(
+            fill_template(
+                "chore(packages): {action} {packages} with {managers} on {host}",
+                "mirror",
+                "ripgrep, fzf",
+                "Cargo Rust, Advance Package Tool",
+                "laptop"
+            ),
+            "chore(packages): mirror ripgrep, fzf with Cargo Rust, Advance Package Tool on laptop"
+        );
+        assert_eq!(
+            fill_template("{action}: {unknown}", "clean", "", "", "laptop"),
+            "clean: {unknown}"
+        );
     }
 }
@@ -0,0 +1,60 @@
+use crate::storage::{Revision, StorageBackendTrait};
+use anyhow::{bail, Context, Result};
+use std::{fs, path::PathBuf};
+
+/// Mirrors stored in a plain directory, which can be synchronized by other tools.
+#[derive(Debug, Clone)]
+pub struct Directory {
+    path: PathBuf,
+    file: String,
+}
+
+impl Directory {
+    /// Instantiate.
+    pub fn new(path: PathBuf, file: String) -> Self {
+        Self { path, file }
+    }
+}
+
+impl StorageBackendTrait for Directory {
+    fn open(&self, _pull: bool) -> Result<()> {
+        println!("Opening Emplace directory: \"{}\".", self.path.display());
+
+        fs::create_dir_all(&self.path).context("creating mirror directory")?;
+
+        Ok(())
+    }
+
+    fn pull(&self) -> Result<()> {
+        // Whatever synchronizes the directory keeps it up to date
+        Ok(())
+    }
+
+    fn save(&self, message: &str) -> Result<()> {
+        // The files are already written, nothing to share
+        println!("{}.", message);
+
+        Ok(())
+    }
+
+    fn sync(&self, _rebase: bool, _push: bool) -> Result<bool> {
+        // Whatever synchronizes the directory shares the changes
+        Ok(true)
+    }
+
+    fn history(&self) -> Result<Vec<Revision>> {
+        bail!("the directory storage backend doesn't keep a history, only git does")
+    }
+
+    fn directory(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn file_path(&self) -> PathBuf {
+        self.path.join(&self.file)
+    }
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,95 @@
+use crate::storage::{Revision, StorageBackendTrait};
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// Mirrors read from a file that can't be changed, like a shared folder without write access.
+#[derive(Debug, Clone)]
+pub struct File {
+    path: PathBuf,
+}
+
+impl File {
+    /// Instantiate from a path or a `file://` URL, pointing to a directory containing the
+    /// mirrors file or to the mirrors file itself.
+    pub fn new(url: &str, file: &str) -> Self {
+        let path = PathBuf::from(url.strip_prefix("file://").unwrap_or(url));
+
+        let path = if path.is_dir() { path.join(file) } else { path };
+
+        Self { path }
+    }
+}
+
+impl StorageBackendTrait for File {
+    fn open(&self, _pull: bool) -> Result<()> {
+        if !self.path.exists() {
+            bail!("mirrors file \"{}\" doesn't exist", self.path.display());
+        }
+
+        println!("Opening Emplace file: \"{}\".", self.path.display());
+
+        Ok(())
+    }
+
+    fn pull(&self) -> Result<()> {
+        // The file is always read directly
+        Ok(())
+    }
+
+    fn save(&self, _message: &str) -> Result<()> {
+        bail!(
+            "mirrors file \"{}\" is read-only, change the storage backend in the config to mirror packages",
+            self.path.display()
+        )
+    }
+
+    fn sync(&self, _rebase: bool, _push: bool) -> Result<bool> {
+        // There's nothing to share
+        Ok(true)
+    }
+
+    fn history(&self) -> Result<Vec<Revision>> {
+        bail!("the file storage backend doesn't keep a history, only git does")
+    }
+
+    fn directory(&self) -> PathBuf {
+        self.path
+            .parent()
+            .map(|parent| parent.to_path_buf())
+            .unwrap_or_default()
+    }
+
+    fn file_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::File;
+    use crate::storage::StorageBackendTrait;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            File::new("file:///mnt/share/mirrors.ron", ".emplace").file_path(),
+            PathBuf::from("/mnt/share/mirrors.ron")
+        );
+        assert_eq!(
+            File::new("/mnt/share/mirrors.ron", ".emplace").directory(),
+            PathBuf::from("/mnt/share")
+        );
+
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            File::new(dir.to_str().unwrap(), ".emplace").file_path(),
+            dir.join(".emplace")
+        );
+        assert!(File::new("/", ".emplace").is_read_only());
+    }
+}
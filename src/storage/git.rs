@@ -0,0 +1,413 @@
+use crate::{
+    config::{RepoConfig, SigningConfig},
+    git::{self, GitError, GitErrorKind},
+    queue::Queue,
+    storage::{self, Revision, StorageBackendTrait},
+};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::{fs, path::PathBuf};
+
+/// Mirrors stored in a git repository that's pulled and pushed.
+#[derive(Debug, Clone)]
+pub struct Git {
+    path: PathBuf,
+    /// Boxed so the other backends don't take up as much space.
+    repo: Box<RepoConfig>,
+    signing: SigningConfig,
+}
+
+impl Git {
+    /// Instantiate.
+    pub fn new(path: PathBuf, repo: RepoConfig, signing: SigningConfig) -> Self {
+        Self {
+            path,
+            repo: Box::new(repo),
+            signing,
+        }
+    }
+
+    /// Whether there's a remote to pull from and push to, otherwise the history only lives on this
+    /// machine.
+    fn has_remote(&self) -> bool {
+        !self.repo.url.is_empty()
+    }
+
+    /// Fetch the remote changes and apply them after verifying them.
+    fn fetch_and_apply(&self, rebase: bool) -> Result<()> {
+        git::fetch(&self.path, &self.repo.transport, &self.repo.branch)?;
+
+        // Check the new commits before anything in them is used
+        self.verify(&format!("HEAD..origin/{}", self.repo.branch))?;
+
+        if rebase {
+            git::rebase(&self.path, &self.repo.transport, &self.repo.branch)?;
+        } else {
+            git::merge(&self.path, &self.repo.transport, &self.repo.branch)?;
+        }
+
+        // Keep the clone shallow, the commits that aren't pushed yet still need their history
+        if let Some(depth) = self.repo.depth {
+            if git::ahead_behind(&self.path, &self.repo.transport)
+                .is_ok_and(|(ahead, _)| ahead == 0)
+            {
+                git::shorten_history(&self.path, &self.repo.transport, &self.repo.branch, depth)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refuse the fetched remote commits that aren't signed by an allowed key before they replace or
+    /// are combined with the local history.
+    pub fn verify_remote(&self) -> Result<()> {
+        if git::has_commits(&self.path, &self.repo.transport) {
+            self.verify(&format!("HEAD..origin/{}", self.repo.branch))
+        } else {
+            // There's no history to compare with, like when cloning
+            self.verify(&format!("origin/{}^!", self.repo.branch))
+        }
+    }
+
+    /// Refuse the commits in the range when they aren't signed by an allowed key.
+    fn verify(&self, range: &str) -> Result<()> {
+        if !self.signing.verify {
+            return Ok(());
+        }
+
+        let unverified = git::signatures(
+            &self.path,
+            &self.repo.transport,
+            range,
+            self.signing.allowed_signers_file.as_deref(),
+        )?
+        .into_iter()
+        .filter(|signature| !signature.is_signed_by(&self.signing.allowed_keys))
+        .map(|signature| signature.commit)
+        .collect::<Vec<_>>();
+
+        if !unverified.is_empty() {
+            bail!(
+                "refusing commits not signed by an allowed key: {}\nhint: add the key to `allowed_keys` in the `[signing]` section of the config if it's trusted",
+                unverified.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl StorageBackendTrait for Git {
+    fn open(&self, pull: bool) -> Result<()> {
+        if self.path.join(".git").exists() {
+            println!("Opening Emplace repo: \"{}\".", self.path.display());
+
+            if pull && self.has_remote() {
+                self.fetch_and_apply(false)
+                    .context("pulling existing repo from config")?;
+            }
+        } else if !self.has_remote() {
+            println!(
+                "Creating local Emplace repo without a remote: \"{}\".",
+                self.path.display()
+            );
+
+            fs::create_dir_all(&self.path).context("creating new directory for repo")?;
+            git::init_repo(&self.path, &self.repo.transport, &self.repo.branch)
+                .context("creating new repo")?;
+        } else {
+            println!(
+                "Cloning Emplace repo \"{}\" to \"{}\".",
+                self.repo.url,
+                self.path.display()
+            );
+
+            fs::create_dir_all(&self.path).context("creating new directory for repo")?;
+            let sparse = self
+                .repo
+                .sparse
+                .then(|| storage::sparse_patterns(&self.repo.file));
+            git::clone_single_branch(
+                &self.path,
+                &self.repo.transport,
+                &self.repo.url,
+                &self.repo.branch,
+                self.repo.depth,
+                sparse.as_deref(),
+            )
+            .context("cloning new repo")?;
+
+            // There's no history to compare with, so the latest state must be trusted
+            if let Err(err) = self.verify("HEAD^!") {
+                fs::remove_dir_all(&self.path).context("removing unverified repo")?;
+
+                return Err(err).context("verifying cloned repo");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pull(&self) -> Result<()> {
+        if !self.has_remote() {
+            return Ok(());
+        }
+
+        self.fetch_and_apply(false).context("pulling repository")?;
+
+        Ok(())
+    }
+
+    fn save(&self, message: &str) -> Result<()> {
+        let mut commit_msg = message.to_string();
+
+        // Add the mirrors to git, the single file is removed when it's converted to a directory
+        for file in [
+            self.repo.file.clone(),
+            storage::directory_name(&self.repo.file),
+        ] {
+            if self.path.join(&file).exists()
+                || git::is_tracked(&self.path, &self.repo.transport, &file)?
+            {
+                git::add_file(&self.path, &self.repo.transport, &file)?;
+            }
+        }
+
+        // Check if there are other changes
+        if git::has_changes(&self.path, &self.repo.transport)? {
+            // Keep the trailers at the end of the message
+            let note = "Including changes of other files in the repository.";
+            commit_msg = match commit_msg.split_once('\n') {
+                Some((subject, rest)) => format!("{}\n\n{}\n{}", subject, note, rest),
+                None => format!("{}\n\n{}", commit_msg, note),
+            };
+            git::add_all_files(&self.path, &self.repo.transport)?;
+        }
+
+        println!("Committing with message \"{}\".", commit_msg);
+        if !git::commit_all(
+            &self.path,
+            &self.repo.transport,
+            &commit_msg,
+            &self.signing,
+            self.repo.author.as_ref(),
+        )? {
+            println!("Nothing changed, not committing.");
+
+            return Ok(());
+        }
+
+        if !self.has_remote() {
+            // The history only lives on this machine
+            return Ok(());
+        }
+        if !self.repo.push {
+            println!("Not pushing, run `emplace sync` to push the commit.");
+
+            return Ok(());
+        }
+
+        println!("Pushing to remote.");
+        if let Err(err) = git::push(&self.path, &self.repo.transport, &self.repo.branch) {
+            // Only a missing connection will solve itself
+            if GitError::kind_of(&err) != Some(GitErrorKind::Network) {
+                return Err(err);
+            }
+
+            // Keep track of the commit so it can be pushed when there's a connection again
+            Queue::modify(&self.path, |queue| queue.push_commit(&commit_msg))?;
+
+            println!(
+                "{}",
+                "Pushing failed, run `emplace sync` to push the commit later.".yellow()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn sync(&self, rebase: bool, push: bool) -> Result<bool> {
+        if !self.has_remote() {
+            println!("No remote configured, run `emplace config set-remote <url>` to add one.");
+
+            return Ok(false);
+        }
+
+        println!("Pulling from remote.");
+        self.fetch_and_apply(rebase)?;
+
+        if !push {
+            println!("Not pushing, run `emplace sync` to push the commits.");
+
+            return Ok(false);
+        }
+
+        println!("Pushing to remote.");
+        git::push(&self.path, &self.repo.transport, &self.repo.branch)?;
+
+        Ok(true)
+    }
+
+    fn history(&self) -> Result<Vec<Revision>> {
+        let directory_name = storage::directory_name(&self.repo.file);
+        let shallow = git::is_shallow(&self.path, &self.repo.transport)?;
+
+        git::file_history(
+            &self.path,
+            &self.repo.transport,
+            &[&self.repo.file, &directory_name],
+        )?
+        .into_iter()
+        .enumerate()
+        .map(|(index, commit)| {
+            let contents = git::show_file(
+                &self.path,
+                &self.repo.transport,
+                &commit.hash,
+                &self.repo.file,
+            )?;
+            let directory = git::list_files(
+                &self.path,
+                &self.repo.transport,
+                &commit.hash,
+                &directory_name,
+            )?
+            .into_iter()
+            .filter(|file| file.ends_with(".ron"))
+            .filter_map(|file| {
+                git::show_file(&self.path, &self.repo.transport, &commit.hash, &file).transpose()
+            })
+            .collect::<Result<_>>()?;
+
+            Ok(Revision {
+                commit,
+                contents,
+                directory,
+                shallow: shallow && index == 0,
+            })
+        })
+        .collect()
+    }
+
+    fn directory(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn file_path(&self) -> PathBuf {
+        self.path.join(&self.repo.file)
+    }
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Git;
+    use crate::{
+        config::{RepoConfig, SigningConfig},
+        storage::StorageBackendTrait,
+    };
+    use std::{fs, path::Path, process::Command};
+
+    /// Run git in the directory with a fixed identity, returning what it printed.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn test_sync_push() {
+        let dir = std::env::temp_dir().join(format!("emplace-sync-{}", std::process::id()));
+        let remote = dir.join("remote.git");
+        let local = dir.join("local");
+        fs::create_dir_all(&remote).unwrap();
+        fs::create_dir_all(&local).unwrap();
+
+        git(
+            &remote,
+            &["init", "--quiet", "--bare", "--initial-branch", "master"],
+        );
+        git(&local, &["init", "--quiet", "--initial-branch", "master"]);
+        git(
+            &local,
+            &["remote", "add", "origin", remote.to_str().unwrap()],
+        );
+        git(
+            &local,
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+        git(
+            &local,
+            &["push", "--quiet", "--set-upstream", "origin", "master"],
+        );
+        git(
+            &local,
+            &["commit", "--quiet", "--allow-empty", "-m", "second"],
+        );
+
+        let storage = |push| {
+            Git::new(
+                local.clone(),
+                RepoConfig {
+                    url: remote.to_string_lossy().to_string(),
+                    push,
+                    ..RepoConfig::default()
+                },
+                SigningConfig::default(),
+            )
+        };
+        let remote_subject = || git(&remote, &["log", "-1", "--format=%s", "master"]);
+
+        // Synchronizing in the background with pushing disabled only pulls
+        assert!(!storage(false).sync(false, false).unwrap());
+        assert_eq!(remote_subject(), "first");
+
+        assert!(storage(false).sync(false, true).unwrap());
+        assert_eq!(remote_subject(), "second");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_shallow() {
+        let dir = std::env::temp_dir().join(format!("emplace-shallow-{}", std::process::id()));
+        let remote = dir.join("remote");
+        fs::create_dir_all(&remote).unwrap();
+
+        git(&remote, &["init", "--quiet", "--initial-branch", "master"]);
+        for contents in ["[]", "[(source: Apt, name: \"fzf\")]"] {
+            fs::write(remote.join(".emplace"), contents).unwrap();
+            git(&remote, &["add", ".emplace"]);
+            git(&remote, &["commit", "--quiet", "-m", "Emplace - mirror"]);
+        }
+        let url = format!("file://{}", remote.display());
+        git(&dir, &["clone", "--quiet", "--depth", "1", &url, "local"]);
+
+        let history = |path| {
+            Git::new(path, RepoConfig::default(), SigningConfig::default())
+                .history()
+                .unwrap()
+        };
+
+        let full = history(remote);
+        assert_eq!(full.len(), 2);
+        assert!(!full[0].shallow);
+
+        // Only the latest version is kept, it doesn't show what changed
+        let shallow = history(dir.join("local"));
+        assert_eq!(shallow.len(), 1);
+        assert!(shallow[0].shallow);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+mod directory;
+mod file;
+mod git;
+
+pub use directory::Directory;
+pub use file::File;
+pub use git::Git;
+
+use crate::{
+    config::{Config, StorageKind},
+    git::Commit,
+    package::ARTIFACTS_DIR,
+};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Enum containing all the ways the mirrors can be stored.
+#[enum_dispatch::enum_dispatch]
+#[derive(Debug, Clone)]
+pub enum StorageBackend {
+    Git,
+    Directory,
+    File,
+}
+
+impl StorageBackend {
+    /// Construct the backend selected in the config.
+    pub fn from_config(config: &Config) -> Self {
+        let directory = PathBuf::from(&config.repo_directory);
+
+        match config.repo.backend {
+            StorageKind::Git => {
+                Git::new(directory, config.repo.clone(), config.signing.clone()).into()
+            }
+            StorageKind::Directory => Directory::new(directory, config.repo.file.clone()).into(),
+            StorageKind::File => File::new(&config.repo.url, &config.repo.file).into(),
+        }
+    }
+
+    /// Location of the directory with a mirrors file per package manager.
+    pub fn mirrors_directory(&self) -> PathBuf {
+        let file_path = self.file_path();
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        file_path.with_file_name(directory_name(&file_name))
+    }
+}
+
+/// Patterns matching the files emplace uses, the rest of the repository isn't checked out when
+/// sparse checkouts are enabled.
+pub fn sparse_patterns(file: &str) -> Vec<String> {
+    vec![
+        format!("/{}", file),
+        format!("/{}/", directory_name(file)),
+        format!("/{}/", ARTIFACTS_DIR),
+        "/emplace.toml".to_string(),
+    ]
+}
+
+/// Name of the directory with a mirrors file per package manager, next to the single mirrors file.
+pub fn directory_name(file: &str) -> String {
+    format!("{}.d", file)
+}
+
+/// A version of the mirrors file.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    /// The commit that created this version.
+    pub commit: Commit,
+    /// Contents of the file, nothing when the file is removed.
+    pub contents: Option<String>,
+    /// Contents of the files in the mirrors directory.
+    pub directory: Vec<String>,
+    /// The older versions aren't available because the clone is shallow.
+    pub shallow: bool,
+}
+
+#[enum_dispatch::enum_dispatch(StorageBackend)]
+pub trait StorageBackendTrait {
+    /// Make sure the storage is available on this machine, retrieving the latest changes when
+    /// asked.
+    fn open(&self, pull: bool) -> Result<()>;
+
+    /// Retrieve the latest changes.
+    fn pull(&self) -> Result<()>;
+
+    /// Persist and share the changes made to the files in the storage.
+    fn save(&self, message: &str) -> Result<()>;
+
+    /// Retrieve the latest changes and share the changes that couldn't be shared before when
+    /// pushing, rebasing them instead of merging when asked. Returns whether the changes are
+    /// shared.
+    fn sync(&self, rebase: bool, push: bool) -> Result<bool>;
+
+    /// All versions of the mirrors file and directory, oldest first.
+    fn history(&self) -> Result<Vec<Revision>>;
+
+    /// Directory where the mirrors file and package files live.
+    fn directory(&self) -> PathBuf;
+
+    /// Location of the mirrors file.
+    fn file_path(&self) -> PathBuf;
+
+    /// Whether the storage can only be read from.
+    fn is_read_only(&self) -> bool;
+}
@@ -0,0 +1,107 @@
+use crate::{
+    config::Config,
+    lock::{FileLock, LOCK_TIMEOUT},
+    queue::Queue,
+    repo::Repo,
+};
+use anyhow::{Context, Result};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::{
+    env,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Share the commits and packages that couldn't be shared without a connection.
+pub fn sync<P>(config_path: P, rebase: bool, background: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    // Get the config
+    let config = Config::from_path_or_new(&config_path)?;
+    let dir = config.repo_directory.clone();
+
+    // Only one process at a time should pull, commit and push
+    let _lock = FileLock::acquire_timeout(
+        Queue::path_for(&dir).with_extension("sync.lock"),
+        LOCK_TIMEOUT,
+    )?;
+
+    // Mirroring in the background follows the push setting, running it explicitly always pushes
+    let push = !background || config.repo.push;
+
+    let result = sync_queue(config, rebase, push);
+    if !background {
+        return result;
+    }
+
+    // There's no terminal to print the error in, so show it at the next prompt
+    let error = result.err().map(|err| format!("{:?}", err));
+    Queue::modify(&dir, |queue| queue.error = error)?;
+
+    Ok(())
+}
+
+/// Run `emplace sync` in a detached process so the terminal isn't blocked.
+pub fn spawn_background<P>(config_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut command = Command::new(env::current_exe().context("finding emplace executable")?);
+    command
+        .arg("-c")
+        .arg(config_path.as_ref())
+        .args(["sync", "--background"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // Don't get killed together with the shell
+    #[cfg(unix)]
+    command.process_group(0);
+
+    command.spawn().context("spawning background process")?;
+
+    Ok(())
+}
+
+fn sync_queue(config: Config, rebase: bool, push: bool) -> Result<()> {
+    let dir = config.repo_directory.clone();
+
+    // Get the repository from the config
+    let repo = Repo::new(config, false)?;
+
+    // Pull the remote changes and push the queued commits when allowed
+    let pushed = repo
+        .sync(rebase, push)
+        .context("synchronizing repository")?;
+
+    // The queued commits are only shared when they are pushed, the queued packages are mirrored
+    // next
+    let mut packages = Queue::modify(&dir, |queue| {
+        if pushed {
+            queue.clear_commits();
+        }
+
+        queue.take_packages()
+    })?;
+
+    // Only keep the packages that haven't been saved on another machine already
+    packages.filter_saved_packages(&repo.read()?);
+    if packages.is_empty() {
+        println!("Everything is synchronized.");
+
+        return Ok(());
+    }
+
+    println!("Mirroring {} queued packages.", packages.len());
+    if let Err(err) = repo.mirror(packages.clone()) {
+        // Don't lose the packages
+        Queue::modify(&dir, |queue| queue.push_packages(&mut packages))?;
+
+        return Err(err).context("mirroring queued packages");
+    }
+
+    Ok(())
+}
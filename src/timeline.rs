@@ -0,0 +1,64 @@
+use crate::{config::Config, repo::Repo};
+use anyhow::Result;
+use colored::Colorize;
+use std::path::Path;
+
+/// Print a timeline of the packages that are added and removed.
+pub fn log<P>(config_path: P, max_count: Option<usize>) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    // Get the config
+    let config = Config::from_path_or_new(&config_path)?;
+
+    // Get the repository from the config
+    let repo = Repo::new(config, true)?;
+
+    let changes = repo.changes()?;
+
+    // Show the newest first, like git does
+    let shown = changes
+        .iter()
+        .rev()
+        // Commits that only change the formatting aren't interesting
+        .filter(|change| !change.added.is_empty() || !change.removed.is_empty())
+        .take(max_count.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    for change in shown.iter() {
+        let commit = &change.revision.commit;
+        println!(
+            "{} {} {} {}",
+            commit.hash[..7.min(commit.hash.len())].yellow(),
+            commit.date,
+            commit.author.bold(),
+            commit
+                .host
+                .as_deref()
+                .map(|host| format!("on {}", host))
+                .unwrap_or_default()
+                .dimmed()
+        );
+
+        for package in change.added.iter() {
+            println!("  {} {}", "+".green(), package.color_full_name());
+        }
+        for package in change.removed.iter() {
+            println!("  {} {}", "-".red(), package.color_full_name());
+        }
+    }
+
+    // The end of the history is reached when less changes than the maximum are shown
+    if changes
+        .first()
+        .is_some_and(|change| change.revision.shallow)
+        && max_count.is_none_or(|max_count| shown.len() < max_count)
+    {
+        println!(
+            "{}",
+            "Older changes aren't available in the shallow clone, run `git fetch --unshallow` in the repository to see them."
+                .dimmed()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::{config::Config, repo::Repo};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use dialoguer::Confirm;
+use std::path::Path;
+
+/// Revert the packages changed by the most recent mirror or clean.
+pub fn undo<P>(config_path: P, yes: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    // Get the config
+    let config = Config::from_path_or_new(&config_path)?;
+
+    // Get the repository from the config
+    let repo = Repo::new(config, true)?;
+
+    // Find the last change made by emplace, changes made by hand should be reverted by hand
+    let changes = repo.changes().context("reading mirror history")?;
+    let change = match changes.iter().rev().find(|change| {
+        change.revision.shallow
+            || (change.revision.commit.is_emplace()
+                && (!change.added.is_empty() || !change.removed.is_empty()))
+    }) {
+        Some(change) if change.revision.shallow => bail!(
+            "the last change made by emplace is older than the history of the shallow clone\nhint: run `git fetch --unshallow` in the repository and remove `depth` from the `[repo]` section of the config"
+        ),
+        Some(change) => change,
+        None => {
+            println!("Nothing to undo.");
+
+            return Ok(());
+        }
+    };
+
+    let commit = &change.revision.commit;
+    println!(
+        "{} \"{}\" {} {}",
+        "Undo".green().bold(),
+        commit.subject,
+        commit.date,
+        commit.author.bold()
+    );
+    for package in change.added.iter() {
+        println!("  {} {}", "-".red(), package.color_full_name());
+    }
+    for package in change.removed.iter() {
+        println!("  {} {}", "+".green(), package.color_full_name());
+    }
+
+    if !yes && !Confirm::new().interact()? {
+        // Exit, we don't need to do anything
+        return Ok(());
+    }
+
+    repo.undo(change)
+}